@@ -1,25 +1,119 @@
 use anchor_lang::prelude::*;
 use ephemeral_rollups_sdk::anchor::{commit, delegate, ephemeral};
-use ephemeral_rollups_sdk::cpi::DelegateConfig;
+use ephemeral_rollups_sdk::cpi::{DelegateConfig, DELEGATION_PROGRAM_ID};
 use ephemeral_rollups_sdk::ephem::{commit_accounts, commit_and_undelegate_accounts};
 
 declare_id!("8rSofJ1enam27SS3btJQAefNQGhUWue8vMMZeUiXscie");
 
 const ARENA_SEED: &[u8] = b"arena";
 const PLAYER_SEED: &[u8] = b"player_v2";
+// Dummies live under their own prefix so init_dummy cannot squat a real wallet's player PDA
+const DUMMY_SEED: &[u8] = b"dummy";
+
+const PLAYER_STATE_SPACE: usize = 8 + PlayerState::INIT_SPACE;
+// Bump whenever the PlayerState layout changes
+const PLAYER_SCHEMA_VERSION: u8 = 17;
+// Size of the first layout that carried schema_version; shorter accounts are untagged
+const FIRST_TAGGED_PLAYER_SPACE: usize = 243;
+// Schema that added PlayerState::arena, and where the field sits in the account
+const ARENA_BINDING_SCHEMA: u8 = 16;
+const PLAYER_ARENA_OFFSET: usize = 509;
+
+// discriminator + authority + player_count + total_kills + is_active + config + winner + arena_id
+// + commit_chunk_next + commit_chunk_total + total_damage_dealt + alive_count + unique_killers + zone
+// + last_commit_at + dirty_since_commit + phase + bounty_target + bounty_xp + session_id + event_seq
+const ARENA_SPACE: usize = 8 + 32 + 4 + 8 + 1 + ArenaConfig::INIT_SPACE + (1 + 32) + 2 + 2 + 2 + 16 + 4 + 4
+    + ZoneState::INIT_SPACE + 8 + 4 + 1 + 32 + 8 + 4 + 8;
+
+// Arena::phase. Live is 0 so arenas grown by migrate_arena come up Live.
+const PHASE_LIVE: u8 = 0;
+const PHASE_WARMUP: u8 = 1;
+const PHASE_ENDED: u8 = 2;
 
 const BASE_HEALTH: u16 = 100;
-const BASE_ATTACK: u16 = 10; // 0.1 * DAMAGE_SCALE(100)
+// On-chain units per 1.0 game damage/HP
+const DAMAGE_SCALE: u32 = 100;
+const BASE_ATTACK: u16 = 10; // 0.1 * DAMAGE_SCALE
+// Base stat gained per health/attack level
+const HEALTH_PER_STAT_LEVEL: u16 = 10;
+const ATTACK_PER_STAT_LEVEL: u16 = 5;
 const LEVEL_SCALE: u64 = 10;
 const MAX_LEVEL: u8 = 100;
 const GHOST_BASE_SECS: i64 = 20;
 const GHOST_PER_LEVEL_SECS: i64 = 1;
 const GHOST_PER_LEVEL_SECS_50PLUS: i64 = 3;
-const DAMAGE_CAP: u32 = 500; // 5.0 * 100
+// Longest ghost timer any level can produce (level 100)
+const MAX_GHOST_SECS: i64 = GHOST_BASE_SECS + 49 * GHOST_PER_LEVEL_SECS + 50 * GHOST_PER_LEVEL_SECS_50PLUS;
+// Extra slack before a too-distant respawn_at is treated as a validator clock fault
+const CLOCK_SKEW_TOLERANCE_SECS: i64 = 60;
+
+const MAX_BATCH_REGISTER: usize = 10;
+// Most players force_reset_arena will reset in one call
+const MAX_BATCH_RESET: usize = 10;
+// Shortest gap between two commits next_commit_recommended_at will suggest
+const MIN_COMMIT_INTERVAL_SECS: i64 = 10;
+// Forfeiting benches the player for longer than any ghost timer
+const FORFEIT_RESPAWN_SECS: i64 = 300;
+// Tombstone after a death: the victim can be neither hit nor respawned for this long
+const DEATH_GRACE_SECS: i64 = 1;
+
+const MATCH_QUEUE_SEED: &[u8] = b"match_queue";
+const MATCH_QUEUE_MAX: usize = 16;
+const MATCH_QUEUE_TIMEOUT_SECS: i64 = 120;
+const ALLOWLIST_SEED: &[u8] = b"allowlist";
+const ALLOWLIST_MAX: usize = 32;
+const TALENT_TABLES_SEED: &[u8] = b"talent_tables";
+const DUEL_SEED: &[u8] = b"duel";
+// Anti-farm: the last ANTI_FARM_SLOTS victims are remembered until the attacker
+// goes ANTI_FARM_WINDOW_SECS without a kill. Kill XP against a victim already in
+// the buffer N times is scaled by ANTI_FARM_XP_BPS[N].
+const ANTI_FARM_SLOTS: usize = 4;
+const ANTI_FARM_WINDOW_SECS: i64 = 600;
+const ANTI_FARM_XP_BPS: [u64; ANTI_FARM_SLOTS + 1] = [10000, 5000, 2500, 1000, 100];
+const DAMAGE_CAP: u32 = 5 * DAMAGE_SCALE;
+const DEFAULT_MAX_HIT_COUNT: u16 = 500;
 
 const XP_PER_KILL_BASE: u64 = 10;
 const XP_PER_KILL_PER_LEVEL: u64 = 3;
 const XP_PER_DEATH: u64 = 5;
+// Sqrt curve matches linear at victim level 1 + KILL_XP_SQRT_PIVOT and falls behind after
+const KILL_XP_SQRT_PIVOT: u64 = 25;
+// Capped curve stops growing past this victim level
+const KILL_XP_CAP_LEVEL: u64 = 30;
+
+const CONTROL_POINT_SEED: &[u8] = b"control_point";
+const POINT_XP_PER_SEC: u64 = 1;
+const POINT_MAX_ACCRUAL_SECS: i64 = 300; // claim at least every 5 min or lose the excess
+
+// Largest single grant_xp award (level 1 to about level 32)
+const MAX_GRANT_XP: u64 = 10_000;
+
+// Longest taunt a single `taunt` call can apply
+const MAX_TAUNT_SECS: i64 = 8;
+
+// Minimum time between two streak-grace saves for the same player
+const STREAK_GRACE_COOLDOWN_SECS: i64 = 300;
+// Default out_of_combat_secs: no hits dealt or taken for this long before base regen starts
+const OUT_OF_COMBAT_SECS: u16 = 5;
+// Most damage one life can absorb across all volleys, as a share of effective
+// max HP (150%). Bounds overkill so Reaper's Arc can't be pumped by huge volleys.
+const LIFE_DAMAGE_CAP_BPS: u32 = 15000;
+// Zone shrink: outside-zone damage per second grows by the base rate every
+// ZONE_RAMP_SECS since the shrink started; one tick takes at most
+// ZONE_MAX_TICK_BPS of effective max HP.
+const ZONE_RAMP_SECS: i64 = 30;
+const ZONE_MAX_TICK_BPS: u32 = 2500;
+const ZONE_TICK_MAX_PLAYERS: usize = 10;
+// Anti-stomp: kill XP falls by this much per level the gap exceeds anti_stomp_gap
+const ANTI_STOMP_BPS_PER_LEVEL: u64 = 1000;
+// Comeback buff: +max HP per level a player trails the top player by more than
+// comeback_level_gap, capped at COMEBACK_MAX_BPS
+const COMEBACK_BPS_PER_LEVEL: u32 = 200;
+const COMEBACK_MAX_BPS: u32 = 3000;
+// Distance falloff: process_attack's distance_bucket indexes falloff_table
+const FALLOFF_BUCKETS: usize = 8;
+// Default heal_cooldown_secs for use_heal
+const HEAL_COOLDOWN_SECS: u16 = 30;
 
 const MAX_TALENT_RANK_TIER1_4: u8 = 5;
 const MAX_TALENT_RANK_CAPSTONE: u8 = 3;
@@ -69,46 +163,153 @@ const ARMOR_BPS: [u32; 5] = [400, 800, 1200, 1600, 2400];
 const HEAVY_HITTER_BPS: [u32; 5] = [400, 800, 1200, 1600, 2400];
 
 // Critical Strike (slot 7): chance 7% per rank (hardcap 35%), multiplier [2.0, 2.2, 2.6, 2.8, 3.0]
-// On-chain: use expected DPS increase = chance * (multiplier - 1)
+const CRIT_CHANCE_BPS: [u32; 5] = [700, 1400, 2100, 2800, 3500];
+const CRIT_MULT_BPS: [u32; 5] = [20000, 22000, 26000, 28000, 30000];
+// On-chain by default: use expected DPS increase = chance * (multiplier - 1)
 // rank 1: 7% * 1.0 = 7%   rank 2: 14% * 1.2 = 16.8%   rank 3: 21% * 1.6 = 33.6%
 // rank 4: 28% * 1.8 = 50.4%   rank 5: 35% * 2.0 = 70%
-const CRIT_EXPECTED_BPS: [u32; 5] = [700, 1680, 3360, 5040, 7000];
+const CRIT_EXPECTED_BPS: [u32; 5] = build_crit_expected(&CRIT_CHANCE_BPS, &CRIT_MULT_BPS);
+// The derived table must stay the one documented above
+const _: () = assert!(
+    CRIT_EXPECTED_BPS[0] == 700
+        && CRIT_EXPECTED_BPS[1] == 1680
+        && CRIT_EXPECTED_BPS[2] == 3360
+        && CRIT_EXPECTED_BPS[3] == 5040
+        && CRIT_EXPECTED_BPS[4] == 7000
+);
+// CappedBurst: most a single crit can add to the hit (+50%)
+const CRIT_BURST_CAP_BPS: u32 = 5000;
 
 // Execute (slot 21): +damage vs ≤50% HP [8%, 16%, 24%, 32%, 48%]
 const EXECUTE_BPS: [u32; 5] = [800, 1600, 2400, 3200, 4800];
 
+// Execute kill XP (slot 21, arena opt-in): bonus kill XP when the victim was in
+// execute range before the killing volley [5%, 10%, 15%, 20%, 30%]
+const EXECUTE_KILL_XP_BPS: [u32; 5] = [500, 1000, 1500, 2000, 3000];
+
 // Vitality Strike (slot 4, capstone): +% of max HP as bonus dmg [0.20%, 0.30%, 0.50%]
 const VITALITY_STRIKE_BPS: [u32; 3] = [20, 30, 50];
 
 // Berserker (slot 24, capstone): +dmg below 33% HP [10%, 20%, 30%]
 const BERSERKER_DMG_BPS: [u32; 3] = [1000, 2000, 3000];
 
+// Last Stand (slot 4 capstone, arena `last_stand`): once per life a lethal hit
+// leaves the player at 1 HP, then they are invulnerable for [1, 2, 3]s
+const LAST_STAND_INVULN_SECS: [i64; 3] = [1, 2, 3];
+
 // Experience (slot 20): +XP gained [10%, 17%, 24%, 32%, 40%]
 const EXPERIENCE_BPS: [u32; 5] = [1000, 1700, 2400, 3200, 4000];
 
+// Reaper's Arc (slot 23): overkill damage on a killing blow converts to bonus XP
+// [10%, 20%, 30%, 40%, 50%] of the overkill, never more than the kill's own XP
+const OVERKILL_XP_BPS: [u32; 5] = [1000, 2000, 3000, 4000, 5000];
+
+// Kill Rush (slot 22): a kill heals the attacker for % of effective max HP [4%, 8%, 12%, 16%, 20%]
+const HEAL_ON_KILL_BPS: [u32; 5] = [400, 800, 1200, 1600, 2000];
+
 // Iron Skin (slot 1): +max HP [10%, 15%, 20%, 25%, 30%]
 const IRON_SKIN_BPS: [u32; 5] = [1000, 1500, 2000, 2500, 3000];
 
+// Lifesteal (slot 3): heal % of damage dealt [5%, 10%, 15%, 20%, 25%], never above 80% max HP
+const LIFESTEAL_BPS: [u32; 5] = [500, 1000, 1500, 2000, 2500];
+const LIFESTEAL_HEAL_CEILING_BPS: u32 = 8000;
+
+// Regeneration (slot 2): 0.3 HP/sec per rank, capped at 1.5, never above 80% max HP.
+// Applied by the server tick; tick_regen only uses it to avoid healing the same HP twice.
+const REGEN_TENTHS_PER_RANK: u32 = 3;
+const REGEN_HEAL_CEILING_BPS: u32 = 8000;
+
+// Second Wind (slot 0 Armor, arena `second_wind`): the turtle build's extra HP
+// at respawn on top of respawn_hp_bps, capped at full [5%, 10%, 15%, 20%, 25%]
+const RESPAWN_HP_TALENT_BPS: [u32; 5] = [500, 1000, 1500, 2000, 2500];
+
+// Counter Attack (slot 16): chance per incoming bullet to fire back [8%, 16%, 24%, 32%, 40%]
+// On-chain: expected value, i.e. hit_count * chance bullets of the victim's own damage
+const COUNTER_CHANCE_BPS: [u32; 5] = [800, 1600, 2400, 3200, 4000];
+
+// Shockwave ult (slot 14, capstone): AoE burst of caster's max HP per target [20%, 30%, 40%]
+const ULT_SHOCKWAVE_BPS: [u32; 3] = [2000, 3000, 4000];
+const ULT_COOLDOWN_SECS: [i64; 3] = [60, 50, 40];
+const ULT_MAX_TARGETS: usize = 5;
+
+// Dash (slot 10): movement is client-side, the cooldown is authoritative [12s, 10s, 8s, 6s, 4s]
+const DASH_COOLDOWN_SECS: [i64; 5] = [12, 10, 8, 6, 4];
+
+// Slots in PlayerState::ability_cooldowns, one per active ability
+const ABILITY_SLOTS: usize = 4;
+const ABILITY_ULT: usize = 0;
+const ABILITY_DASH: usize = 1;
+const ABILITY_HEAL: usize = 2;
+
+// Talent slots per tree, in prerequisite order (see talent_prerequisite)
+const TREE_TANK: usize = 0;
+const TREE_FIREPOWER: usize = 1;
+const TREE_BRAWLER: usize = 2;
+const TREE_MASS_DAMAGE: usize = 3;
+const TREE_BLOOD: usize = 4;
+const TREE_SLOTS: [[u8; 5]; 5] = [
+    [0, 1, 2, 3, 4],
+    [5, 6, 7, 8, 9],
+    [10, 11, 12, 13, 14],
+    [15, 16, 19, 18, 17],
+    [20, 21, 22, 23, 24],
+];
+// Tier-5 slot of each tree; at most `max_capstones` of them may hold ranks
+const CAPSTONE_SLOTS: [u8; 5] = [4, 9, 14, 17, 24];
+
 fn lookup_bps(rank: u8, table: &[u32]) -> u32 {
     if rank == 0 || rank as usize > table.len() { return 0; }
     table[rank as usize - 1]
 }
 
-fn calc_level(xp: u64) -> u8 {
+/// A stored talent rank as the arena sees it: 0 in `talents_disabled` arenas.
+fn talent_rank(rank: u8, config: &ArenaConfig) -> u8 {
+    if config.talents_disabled { 0 } else { rank }
+}
+
+// Cumulative XP needed to leave each level: entry N is the total XP at which
+// level N + 2 is reached. Built at compile time so calc_level is a lookup.
+const XP_THRESHOLDS: [u64; MAX_LEVEL as usize - 1] = build_xp_thresholds();
+
+/// Expected crit bonus per rank: chance * (multiplier - 1), all in bps.
+const fn build_crit_expected(chance: &[u32; 5], mult: &[u32; 5]) -> [u32; 5] {
+    let mut table = [0u32; 5];
+    let mut i = 0;
+    while i < 5 {
+        table[i] = chance[i] * (mult[i] - 10000) / 10000;
+        i += 1;
+    }
+    table
+}
+
+const fn build_xp_thresholds() -> [u64; MAX_LEVEL as usize - 1] {
+    let mut table = [0u64; MAX_LEVEL as usize - 1];
     let mut total_xp: u64 = 0;
     let mut penalty_bp: u64 = 10000; // basis points (10000 = 1.0x)
-    for lvl in 1..(MAX_LEVEL as u64) {
+    let mut lvl: u64 = 1;
+    while lvl < MAX_LEVEL as u64 {
         let base_cost = (2 * lvl - 1) * LEVEL_SCALE;
         if lvl > 50 {
             penalty_bp = penalty_bp * 106 / 100; // compound 6% per level
         }
-        let cost = base_cost * penalty_bp / 10000;
-        total_xp += cost;
-        if xp < total_xp {
-            return lvl as u8;
-        }
+        total_xp += base_cost * penalty_bp / 10000;
+        table[lvl as usize - 1] = total_xp;
+        lvl += 1;
+    }
+    table
+}
+
+fn calc_level(xp: u64) -> u8 {
+    // Thresholds already passed, plus the starting level
+    XP_THRESHOLDS.partition_point(|&t| t <= xp) as u8 + 1
+}
+
+/// Total XP at which `level` is first reached (inverse of `calc_level`).
+fn level_floor_xp(level: u8) -> u64 {
+    match level.min(MAX_LEVEL) {
+        0 | 1 => 0,
+        lvl => XP_THRESHOLDS[lvl as usize - 2],
     }
-    MAX_LEVEL
 }
 
 fn calc_ghost_secs(level: u8) -> i64 {
@@ -120,6 +321,40 @@ fn calc_ghost_secs(level: u8) -> i64 {
     }
 }
 
+/// Extra arena seed for `arena_id`. Arena 0 maps to the original singleton
+/// PDA (`[ARENA_SEED]`) so existing deployments keep their address.
+fn arena_id_seed(arena_id: u16) -> Vec<u8> {
+    if arena_id == 0 {
+        Vec::new()
+    } else {
+        arena_id.to_le_bytes().to_vec()
+    }
+}
+
+/// PDA prefix for a PlayerState: dummies and real players never share an address.
+fn player_seed(is_dummy: bool) -> &'static [u8] {
+    if is_dummy {
+        DUMMY_SEED
+    } else {
+        PLAYER_SEED
+    }
+}
+
+/// Delegated accounts are owned by the delegation program. Delegating one again
+/// fails inside the SDK, so the delegate instructions check this first and
+/// treat a repeat as a no-op (server restarts re-issue delegation).
+fn is_delegated(account: &AccountInfo, delegation_program: &AccountInfo) -> bool {
+    account.owner == delegation_program.key
+}
+
+/// Validator time with a basic sanity check. The ER validator's clock is trusted
+/// for ghost timers, so a non-positive timestamp is rejected outright.
+fn checked_now() -> Result<i64> {
+    let now = Clock::get()?.unix_timestamp;
+    require!(now > 0, CombatError::ClockAnomaly);
+    Ok(now)
+}
+
 fn calc_talent_points(level: u8) -> u16 {
     // 1 point at level 1, then every 2 levels: levels 1,3,5,...,99 → 50 points at level 100
     let mut points: u16 = 0;
@@ -133,418 +368,1371 @@ fn calc_talent_points(level: u8) -> u16 {
     points
 }
 
-fn effective_max_health(player: &PlayerState) -> u32 {
-    let base = player.max_health as u32;
-    let iron_skin_bonus = lookup_bps(player.talent_heavy_hitter, &IRON_SKIN_BPS);
-    base * (10000 + iron_skin_bonus) / 10000
+/// Points a player at `level` may spend in this arena: the level budget, capped
+/// by `max_total_points` when the arena sets one.
+fn talent_budget(level: u8, config: &ArenaConfig) -> u16 {
+    let points = calc_talent_points(level);
+    if config.max_total_points > 0 {
+        points.min(config.max_total_points)
+    } else {
+        points
+    }
+}
+
+/// A build is affordable while the talent points the player has spent fit within
+/// the budget granted by the level their `xp` resolves to.
+fn require_build_affordable(player: &PlayerState, xp: u64) -> Result<()> {
+    let budget = calc_talent_points(calc_level(xp));
+    require!(
+        player.total_talent_points_spent() <= budget,
+        CombatError::BuildExceedsBudget
+    );
+    Ok(())
 }
 
-/// Compute per-hit damage from attacker's on-chain state against a victim.
-/// All math uses u32 with 10000 basis-point scaling to avoid floats.
-fn compute_hit_damage(attacker: &PlayerState, victim: &PlayerState) -> u16 {
-    let mut dmg: u32 = attacker.attack_power as u32;
+/// Strip talent ranks until the build fits the player's current level. Each
+/// step removes one rank from the deepest occupied tier across all trees
+/// (capstones first, lower tree index on ties), so no remaining rank loses its
+/// prerequisite. Returns the slot of every rank removed, in order.
+fn refund_overflow(player: &mut PlayerState) -> Vec<u8> {
+    let budget = calc_talent_points(calc_level(player.xp));
+    let mut refunded = Vec::new();
+    while player.total_talent_points_spent() > budget {
+        let deepest = TREE_SLOTS
+            .iter()
+            .filter_map(|tree| {
+                tree.iter().enumerate().rev().find(|(_, &slot)| player.get_talent(slot) > 0)
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(a.1)));
+        let Some((_, &slot)) = deepest else { break };
+        player.set_talent(slot, player.get_talent(slot) - 1);
+        refunded.push(slot);
+    }
+    refunded
+}
 
-    // Heavy Hitter (slot 5 = talent_swift): +X% bullet damage
-    let hh = lookup_bps(attacker.talent_swift, &HEAVY_HITTER_BPS);
-    if hh > 0 {
-        dmg = dmg * (10000 + hh) / 10000;
+/// Run `refund_overflow` and report it, if anything was refunded.
+fn refund_and_emit(player: &mut PlayerState) {
+    let slots = refund_overflow(player);
+    if !slots.is_empty() {
+        msg!("Player {} refunded {} talent ranks", player.wallet, slots.len());
+        emit!(TalentRefundEvent { wallet: player.wallet, slots });
+    }
+}
+
+/// Clamp every stored rank to `max_rank_for_talent`, logging each slot fixed.
+/// Out-of-range ranks (e.g. a capstone at 4-5) otherwise read as 0 in the
+/// lookup tables. Returns how many slots were clamped.
+fn sanitize_talent_ranks(player: &mut PlayerState) -> u8 {
+    let mut clamped = 0;
+    for slot in 0..25u8 {
+        let rank = player.get_talent(slot);
+        let max = max_rank_for_talent(slot);
+        if rank > max {
+            msg!("Player {} slot {}: rank {} clamped to {}", player.wallet, slot, rank, max);
+            player.set_talent(slot, max);
+            clamped += 1;
+        }
     }
+    clamped
+}
 
-    // Berserker (slot 24 = talent_frenzy): bonus damage below 33% HP
-    if attacker.talent_frenzy > 0 {
-        let eff_max = effective_max_health(attacker);
-        let threshold = eff_max * 3300 / 10000;
-        if (attacker.health as u32) <= threshold {
-            let bonus = lookup_bps(attacker.talent_frenzy, &BERSERKER_DMG_BPS);
-            dmg = dmg * (10000 + bonus) / 10000;
+/// Validate a complete build (rank per slot) for a player at `level` against
+/// the arena's capstone rules. Every failure logs the offending slot before
+/// returning its error.
+fn validate_loadout(ranks: &[u8; 25], level: u8, config: &ArenaConfig) -> Result<()> {
+    if config.talents_disabled && ranks.iter().any(|&r| r > 0) {
+        msg!("Loadout: talents are disabled in this arena");
+        return err!(CombatError::TalentsDisabled);
+    }
+    let max_capstones = config.max_capstones;
+    for (slot, &rank) in ranks.iter().enumerate() {
+        let slot = slot as u8;
+        if rank == 0 {
+            continue;
+        }
+        if rank > max_rank_for_talent(slot) {
+            msg!("Loadout slot {}: rank {} above max {}", slot, rank, max_rank_for_talent(slot));
+            return err!(CombatError::TalentMaxed);
+        }
+        // Every earlier tier of the tree needs a rank, not just the immediate prereq
+        let mut prereq = talent_prerequisite(slot);
+        while let Some(p) = prereq {
+            if ranks[p as usize] == 0 {
+                msg!("Loadout slot {}: prerequisite slot {} has no rank", slot, p);
+                return err!(CombatError::PrerequisiteNotMet);
+            }
+            prereq = talent_prerequisite(p);
         }
     }
 
-    // Vitality Strike (slot 4 = talent_armor): +X% of max HP as bonus damage
-    if attacker.talent_armor > 0 {
-        let eff_max = effective_max_health(attacker);
-        let vs = lookup_bps(attacker.talent_armor, &VITALITY_STRIKE_BPS);
-        dmg += eff_max * vs / 10000;
+    let capstones = CAPSTONE_SLOTS.iter().filter(|&&id| ranks[id as usize] > 0).count();
+    if capstones > 0 && level < config.capstone_min_level {
+        msg!("Loadout: capstones need level {}, player is {}", config.capstone_min_level, level);
+        return err!(CombatError::CapstoneLevelLocked);
+    }
+    if capstones > max_capstones as usize {
+        msg!("Loadout: {} capstones, arena allows {}", capstones, max_capstones);
+        return err!(CombatError::MaxCapstones);
+    }
+
+    let spent: u16 = ranks.iter().map(|&r| r as u16).sum();
+    let budget = talent_budget(level, config);
+    if spent > budget {
+        msg!("Loadout: {} points spent, level {} grants {}", spent, level, budget);
+        return err!(CombatError::BuildExceedsBudget);
+    }
+    Ok(())
+}
+
+/// Every rule `allocate_talent` enforces for one more rank in `talent_id`:
+/// - Talents enabled in the arena
+/// - Level-based point budget, capped by the arena's `max_total_points`
+/// - Max rank per talent
+/// - Prerequisite chain (tier N requires tier N-1)
+/// - Capstone level gate and limit (arena `max_capstones` of 5, 2 by default)
+///
+/// Shared with `can_allocate_talent`, so it returns the bare error variant.
+fn check_talent_allocation(
+    player: &PlayerState,
+    talent_id: u8,
+    config: &ArenaConfig,
+) -> core::result::Result<(), CombatError> {
+    if !player.initialized {
+        return Err(CombatError::NotInitialized);
+    }
+    if config.talents_disabled {
+        return Err(CombatError::TalentsDisabled);
+    }
+    if talent_id > 24 {
+        return Err(CombatError::InvalidTalentId);
     }
 
-    // Cap at 5.0 game-damage (500 on-chain)
-    dmg = dmg.min(DAMAGE_CAP);
+    let level = calc_level(player.xp);
+    if player.total_talent_points_spent() >= talent_budget(level, config) {
+        return Err(CombatError::NoTalentPoints);
+    }
 
-    // Critical Strike (slot 7 = talent_evasion): deterministic expected value
-    // Instead of random crits, apply the average DPS increase
-    if attacker.talent_evasion > 0 {
-        let crit_ev = lookup_bps(attacker.talent_evasion, &CRIT_EXPECTED_BPS);
-        dmg = dmg * (10000 + crit_ev) / 10000;
+    let current = player.get_talent(talent_id);
+    // Above the max can only come from a corrupted account; lookup_bps would read
+    // it as rank 0, so say so instead of reporting a maxed talent.
+    if current > max_rank_for_talent(talent_id) {
+        return Err(CombatError::TalentRankCorrupt);
+    }
+    if current == max_rank_for_talent(talent_id) {
+        return Err(CombatError::TalentMaxed);
     }
 
-    // Execute (slot 21 = talent_homing): +X% damage vs targets ≤50% HP
-    if attacker.talent_homing > 0 {
-        let victim_eff_max = effective_max_health(victim);
-        if (victim.health as u32) * 2 <= victim_eff_max {
-            let exec = lookup_bps(attacker.talent_homing, &EXECUTE_BPS);
-            dmg = dmg * (10000 + exec) / 10000;
+    // Prerequisite: each talent requires at least 1 rank in the previous talent of its tree
+    if let Some(prereq_id) = talent_prerequisite(talent_id) {
+        if player.get_talent(prereq_id) == 0 {
+            return Err(CombatError::PrerequisiteNotMet);
         }
     }
 
-    // Armor (slot 0 = talent_iron_skin on victim): -X% incoming damage
-    if victim.talent_iron_skin > 0 {
-        let armor = lookup_bps(victim.talent_iron_skin, &ARMOR_BPS);
-        dmg = dmg * (10000 - armor.min(9999)) / 10000;
+    if CAPSTONE_SLOTS.contains(&talent_id) {
+        // Capstone level gate, independent of the points budget
+        if level < config.capstone_min_level {
+            msg!("Capstone {} needs level {}, player is {}", talent_id, config.capstone_min_level, level);
+            return Err(CombatError::CapstoneLevelLocked);
+        }
+        // Capstone limit: arena-configured share of the 5 capstone talents (slots 4, 9, 14, 17, 24)
+        if current == 0 {
+            let chosen = capstone_count(player);
+            if chosen >= config.max_capstones as usize {
+                msg!("Capstone limit reached: {} of {}", chosen, config.max_capstones);
+                return Err(CombatError::MaxCapstones);
+            }
+        }
     }
+    Ok(())
+}
 
-    dmg.max(1) as u16
+/// Capstone slots (4, 9, 14, 17, 24) holding at least one rank.
+fn capstone_count(player: &PlayerState) -> usize {
+    CAPSTONE_SLOTS.iter().filter(|&&id| player.get_talent(id) > 0).count()
 }
 
-#[ephemeral]
-#[program]
-pub mod hodlwarz_combat {
-    use super::*;
+/// The arena's `dual_capstone_bps` for players with two or more capstones, else 0.
+fn dual_capstone_bps(player: &PlayerState, config: &ArenaConfig) -> i32 {
+    if config.talents_disabled || config.dual_capstone_bps == 0 || capstone_count(player) < 2 {
+        return 0;
+    }
+    config.dual_capstone_bps as i32
+}
 
-    pub fn init_arena(ctx: Context<InitArena>) -> Result<()> {
-        let arena = &mut ctx.accounts.arena;
-        arena.authority = ctx.accounts.authority.key();
-        arena.player_count = 0;
-        arena.total_kills = 0;
-        arena.is_active = true;
-        msg!("Arena initialized by {}", arena.authority);
-        Ok(())
+/// Tree synergy: a player with at least `tree_bonus_threshold` points in one tree
+/// gets that tree's `tree_bonus_bps`. Tank/Brawler grant max HP, Firepower/Mass
+/// Damage grant damage, Blood grants kill XP.
+fn tree_synergy_bps(player: &PlayerState, config: &ArenaConfig, tree: usize) -> u32 {
+    if config.talents_disabled
+        || config.tree_bonus_threshold == 0
+        || player.points_in_tree(tree) < config.tree_bonus_threshold
+    {
+        return 0;
     }
+    config.tree_bonus_bps[tree] as u32
+}
 
-    pub fn register_player(ctx: Context<RegisterPlayer>, wallet: Pubkey) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        player.wallet = wallet;
-        player.health = BASE_HEALTH;
-        player.max_health = BASE_HEALTH;
-        player.attack_power = BASE_ATTACK;
-        player.xp = 0;
-        player.kills = 0;
-        player.deaths = 0;
-        player.health_level = 1;
-        player.attack_level = 1;
-        player.is_alive = true;
-        player.respawn_at = 0;
-        player.initialized = true;
-        player.talent_iron_skin = 0;
-        player.talent_heavy_hitter = 0;
-        player.talent_regeneration = 0;
-        player.talent_lifesteal = 0;
-        player.talent_armor = 0;
-        player.talent_swift = 0;
-        player.talent_rapid_fire = 0;
-        player.talent_evasion = 0;
-        player.talent_quick_respawn = 0;
-        player.talent_momentum = 0;
-        player.talent_weakspot = 0;
-        player.talent_critical_strike = 0;
-        player.talent_focus_fire = 0;
-        player.talent_multi_shot = 0;
-        player.talent_dual_cannon = 0;
-        player.talent_deflect = 0;
-        player.talent_absorb = 0;
-        player.talent_last_stand = 0;
-        player.talent_cloak = 0;
-        player.talent_dash = 0;
-        player.talent_rampage = 0;
-        player.talent_homing = 0;
-        player.talent_ricochet = 0;
-        player.talent_deathbomb = 0;
-        player.talent_frenzy = 0;
-        player.manual_build = false;
-        msg!("Player {} registered", wallet);
-        Ok(())
+/// Bitmask of trees whose synergy is active (bit N = tree N).
+fn active_synergies(player: &PlayerState, config: &ArenaConfig) -> u8 {
+    (0..TREE_SLOTS.len())
+        .filter(|&tree| tree_synergy_bps(player, config, tree) > 0)
+        .fold(0, |mask, tree| mask | (1 << tree))
+}
+
+fn init_base_player(player: &mut PlayerState, wallet: Pubkey) {
+    player.wallet = wallet;
+    player.health = BASE_HEALTH;
+    player.max_health = BASE_HEALTH;
+    player.attack_power = BASE_ATTACK;
+    player.xp = 0;
+    player.kills = 0;
+    player.deaths = 0;
+    player.health_level = 1;
+    player.attack_level = 1;
+    player.is_alive = true;
+    player.respawn_at = 0;
+    player.initialized = true;
+    player.talent_iron_skin = 0;
+    player.talent_heavy_hitter = 0;
+    player.talent_regeneration = 0;
+    player.talent_lifesteal = 0;
+    player.talent_armor = 0;
+    player.talent_swift = 0;
+    player.talent_rapid_fire = 0;
+    player.talent_evasion = 0;
+    player.talent_quick_respawn = 0;
+    player.talent_momentum = 0;
+    player.talent_weakspot = 0;
+    player.talent_critical_strike = 0;
+    player.talent_focus_fire = 0;
+    player.talent_multi_shot = 0;
+    player.talent_dual_cannon = 0;
+    player.talent_deflect = 0;
+    player.talent_absorb = 0;
+    player.talent_last_stand = 0;
+    player.talent_cloak = 0;
+    player.talent_dash = 0;
+    player.talent_rampage = 0;
+    player.talent_homing = 0;
+    player.talent_ricochet = 0;
+    player.talent_deathbomb = 0;
+    player.talent_frenzy = 0;
+    player.manual_build = false;
+    player.is_dummy = false;
+    player.total_damage_taken = 0;
+    player.ult_ready_at = 0;
+    player.out_of_match = false;
+    player.top_damager = Pubkey::default();
+    player.top_damage = 0;
+    player.current_streak = 0;
+    player.last_killed_by = Pubkey::default();
+    player.taunted_by = Pubkey::default();
+    player.taunt_until = 0;
+    player.streak_grace_used_at = 0;
+    player.schema_version = PLAYER_SCHEMA_VERSION;
+    player.dash_ready_at = 0;
+    player.got_kill_this_session = false;
+    player.recent_victims = [Pubkey::default(); ANTI_FARM_SLOTS];
+    player.recent_victims_head = 0;
+    player.last_kill_at = 0;
+    player.last_combat_at = 0;
+    player.last_regen_at = 0;
+    player.heal_ready_at = 0;
+    player.damage_taken_this_life = 0;
+    player.in_zone = true;
+    player.last_zone_tick_at = 0;
+    player.last_stand_used = false;
+    player.invulnerable_until = 0;
+    player.respawn_pending = false;
+    player.ability_cooldowns = [0; ABILITY_SLOTS];
+    player.skin_id = 0;
+    player.comeback_bps = 0;
+    player.duel = Pubkey::default();
+    player.death_grace_until = 0;
+    player.arena = Pubkey::default();
+    player.kill_session = 0;
+}
+
+/// Back to a fresh registration, keeping the wallet, arena, dummy flag, skin
+/// and kill session (a reset mid-session must not count as a new killer).
+fn reset_to_base(player: &mut PlayerState) {
+    let is_dummy = player.is_dummy;
+    let skin_id = player.skin_id;
+    let arena = player.arena;
+    let kill_session = player.kill_session;
+    init_base_player(player, player.wallet);
+    player.is_dummy = is_dummy;
+    player.skin_id = skin_id;
+    player.arena = arena;
+    player.kill_session = kill_session;
+}
+
+/// Whether `reset_player` may run. A reset revives a dead player, so during a
+/// live elimination round it is closed to the dead just like respawning. It
+/// would also drop the player's side of a duel, so duelists have to close the
+/// duel first.
+fn check_reset(player: &PlayerState, arena: &Arena) -> Result<()> {
+    require!(player.initialized, CombatError::NotInitialized);
+    require!(player.duel == Pubkey::default(), CombatError::DuelRestricted);
+    require!(
+        player.is_alive || !(arena.config.elimination_mode && arena.is_active),
+        CombatError::EliminationMode
+    );
+    Ok(())
+}
+
+/// Starting profile for a fresh or reset player: the arena's `start_xp`, both
+/// stat levels at `start_level`, base stats derived from those and full HP.
+fn apply_start_profile(player: &mut PlayerState, config: &ArenaConfig) {
+    player.xp = config.start_xp;
+    player.health_level = config.start_level;
+    player.attack_level = config.start_level;
+    sync_stats_to_levels(player, config);
+    player.health = effective_max_health(player, config).min(u16::MAX as u32) as u16;
+}
+
+/// `health_level`/`attack_level` are the source of truth for the base stats;
+/// this rewrites `max_health`/`attack_power` from them. A living player gains
+/// HP along with any max HP increase and never ends above the effective max.
+fn sync_stats_to_levels(player: &mut PlayerState, config: &ArenaConfig) {
+    let max_health = BASE_HEALTH + (player.health_level.max(1) as u16 - 1) * HEALTH_PER_STAT_LEVEL;
+    if player.is_alive && max_health > player.max_health {
+        player.health = player.health.saturating_add(max_health - player.max_health);
     }
+    player.max_health = max_health;
+    player.attack_power = BASE_ATTACK + (player.attack_level.max(1) as u16 - 1) * ATTACK_PER_STAT_LEVEL;
+    let eff_max = effective_max_health(player, config).min(u16::MAX as u32) as u16;
+    player.health = player.health.min(eff_max);
+}
 
-    pub fn delegate_arena(ctx: Context<DelegateArenaCtx>) -> Result<()> {
-        ctx.accounts.delegate_arena(
-            &ctx.accounts.payer,
-            &[ARENA_SEED],
-            DelegateConfig {
-                validator: ctx.remaining_accounts.first().map(|a| a.key()),
-                ..Default::default()
-            },
-        )?;
-        msg!("Arena delegated to ER");
-        Ok(())
+/// After an XP gain in an `auto_level` arena, health and attack level follow
+/// the XP level. Manual-level arenas skip this so XP never hands out stat
+/// levels that `upgrade_stat` charges for. The stats themselves only move
+/// with `auto_level_sync`; otherwise they catch up at the next respawn or
+/// upgrade.
+fn auto_level(player: &mut PlayerState, config: &ArenaConfig) {
+    if !config.auto_level {
+        return;
     }
+    let new_level = calc_level(player.xp);
+    player.health_level = new_level;
+    player.attack_level = new_level;
+    if config.auto_level_sync {
+        sync_stats_to_levels(player, config);
+    }
+}
 
-    pub fn delegate_player(ctx: Context<DelegatePlayerCtx>, wallet: Pubkey) -> Result<()> {
-        ctx.accounts.delegate_player_state(
-            &ctx.accounts.payer,
-            &[PLAYER_SEED, wallet.as_ref()],
-            DelegateConfig {
-                validator: ctx.remaining_accounts.first().map(|a| a.key()),
-                ..Default::default()
-            },
-        )?;
-        msg!("Player {} delegated to ER", wallet);
-        Ok(())
+/// HP a dead player comes back with. Talents, loadouts, stat upgrades and
+/// resets are all allowed while dead, so the base stats are re-derived from the
+/// levels first and the effective max reflects the build as it is now.
+fn respawn_health(player: &mut PlayerState, config: &ArenaConfig) -> u16 {
+    sync_stats_to_levels(player, config);
+    let eff_max = effective_max_health(player, config).min(u16::MAX as u32);
+    let mut bps = config.respawn_hp_bps as u32;
+    if config.second_wind {
+        bps += lookup_bps(talent_rank(player.talent_iron_skin, config), &RESPAWN_HP_TALENT_BPS);
     }
+    (eff_max * bps.min(10000) / 10000).max(1) as u16
+}
 
-    /// Process combat: the ER computes damage from on-chain talent state.
-    /// Server sends hit_count (how many bullets connected), chain resolves damage.
-    /// The server CANNOT dictate damage — the chain is authoritative.
-    pub fn process_attack(ctx: Context<ProcessAttack>, hit_count: u16) -> Result<()> {
-        let attacker = &mut ctx.accounts.attacker;
-        let victim = &mut ctx.accounts.victim;
-        let arena = &mut ctx.accounts.arena;
+fn effective_max_health(player: &PlayerState, config: &ArenaConfig) -> u32 {
+    let mut base = player.max_health as u32;
+    // A zeroed max_health (e.g. from a bad migration) would make every HP-ratio
+    // check degenerate; fall back to the starting pool instead.
+    if base == 0 {
+        msg!("WARN: player {} has max_health 0, using BASE_HEALTH", player.wallet);
+        base = BASE_HEALTH as u32;
+    }
+    // Arena HP scale applies to the base pool; Iron Skin and synergy build on it
+    base = (base * config.hp_scale_bps as u32 / 10000).max(1);
+    let iron_skin_bonus = lookup_bps(talent_rank(player.talent_heavy_hitter, config), &IRON_SKIN_BPS)
+        * config.iron_skin_scale_bps as u32
+        / 10000;
+    let synergy = tree_synergy_bps(player, config, TREE_TANK)
+        + tree_synergy_bps(player, config, TREE_BRAWLER);
+    let comeback = if config.comeback_buff {
+        (player.comeback_bps as u32).min(COMEBACK_MAX_BPS)
+    } else {
+        0
+    };
+    let bps = (10000 + iron_skin_bonus + synergy + comeback) as i32 + dual_capstone_bps(player, config);
+    (base * bps.max(1) as u32 / 10000).max(1)
+}
 
-        require!(attacker.initialized, CombatError::NotInitialized);
-        require!(victim.initialized, CombatError::NotInitialized);
-        require!(attacker.is_alive, CombatError::AttackerDead);
-        require!(victim.is_alive, CombatError::VictimDead);
-        require!(arena.is_active, CombatError::ArenaInactive);
-        require!(hit_count > 0 && hit_count <= 500, CombatError::InvalidHitCount);
+fn in_execute_range(victim: &PlayerState, victim_eff_max: u32, config: &ArenaConfig) -> bool {
+    (victim.health as u64) * 10000 <= victim_eff_max as u64 * config.execute_threshold_bps as u64
+}
 
-        let damage_per_hit = compute_hit_damage(attacker, victim);
-        let total_damage = (damage_per_hit as u32)
-            .saturating_mul(hit_count as u32)
-            .min(u16::MAX as u32) as u16;
+/// The program's one source of randomness: FNV-1a 64 over `part_0 || part_1 || ...`,
+/// finished with splitmix64. Plain integer ops, so the server can reproduce any
+/// roll from the same parts. Callers mix in the clock themselves; nothing here is
+/// secret, only reproducible.
+pub fn deterministic_seed(seed_parts: &[&[u8]]) -> u64 {
+    let mut h: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in seed_parts.iter().flat_map(|part| part.iter()) {
+        h ^= *byte as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    let mut z = h.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
-        if victim.health <= total_damage {
-            victim.health = 0;
-            victim.is_alive = false;
-            victim.deaths += 1;
-            victim.xp += XP_PER_DEATH;
-            let victim_lvl = calc_level(victim.xp.saturating_sub(XP_PER_DEATH));
-            victim.respawn_at = Clock::get()?.unix_timestamp + calc_ghost_secs(victim_lvl);
+/// `deterministic_seed(seed_parts) % modulo`, a roll in [0, modulo).
+pub fn deterministic_roll(seed_parts: &[&[u8]], modulo: u32) -> u32 {
+    (deterministic_seed(seed_parts) % modulo.max(1) as u64) as u32
+}
 
-            attacker.kills += 1;
+/// Seed for rolled crits: the attacker and victim wallets, the clock
+/// (unix seconds, LE), the slot (LE, so volleys within one second differ) and
+/// the victim's damage taken so far (LE, moves every hit).
+fn crit_seed(attacker: &PlayerState, victim: &PlayerState, now: i64, slot: u64) -> u64 {
+    deterministic_seed(&[
+        attacker.wallet.as_ref(),
+        victim.wallet.as_ref(),
+        &now.to_le_bytes(),
+        &slot.to_le_bytes(),
+        &victim.total_damage_taken.to_le_bytes(),
+    ])
+}
 
-            // XP scales with victim level
-            let victim_level = calc_level(victim.xp.saturating_sub(XP_PER_DEATH)) as u64;
-            let mut kill_xp = XP_PER_KILL_BASE + victim_level.saturating_sub(1) * XP_PER_KILL_PER_LEVEL;
+/// Reduce a crit seed to a roll in [0, 10000).
+fn crit_roll(seed: u64) -> u32 {
+    (seed % 10000) as u32
+}
 
-            // Bounty: 2x XP for killing level 50+ players
-            if victim_level >= 50 {
-                kill_xp *= 2;
-            }
+/// The arena's talent tables. Callers must pass the account at its PDA so an
+/// override the authority has set can't be skipped; until `init_talent_tables`
+/// creates it the address is empty and the compiled tables apply.
+fn load_talent_tables(info: &AccountInfo) -> Result<TalentTableValues> {
+    if info.data_is_empty() {
+        return Ok(TalentTableValues::default());
+    }
+    require_keys_eq!(*info.owner, crate::ID, CombatError::InvalidConfig);
+    let tables = TalentTables::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    Ok(tables.values)
+}
+
+/// Whether a rolled crit lands on one hit seeded with `seed`. Never under
+/// CritModel::ExpectedValue, which folds crits into every hit instead.
+fn crit_lands(rank: u8, model: CritModel, chance_bps: &[u32; 5], seed: u64) -> bool {
+    rank > 0
+        && model != CritModel::ExpectedValue
+        && crit_roll(seed) < chance_bps[rank.min(5) as usize - 1]
+}
+
+/// Crits among the `hit_count` hits of a volley. Each hit rolls on its own
+/// seed (the volley seed and the hit index), so a volley crits in part rather
+/// than all or nothing.
+fn volley_crits(rank: u8, model: CritModel, chance_bps: &[u32; 5], seed: u64, hit_count: u16) -> u16 {
+    if rank == 0 || model == CritModel::ExpectedValue {
+        return 0;
+    }
+    (0..hit_count)
+        .filter(|i| crit_lands(rank, model, chance_bps, deterministic_seed(&[&seed.to_le_bytes(), &i.to_le_bytes()])))
+        .count() as u16
+}
 
-            // Experience talent (slot 20 = talent_rampage): +X% XP
-            let exp_bonus = lookup_bps(attacker.talent_rampage, &EXPERIENCE_BPS);
-            if exp_bonus > 0 {
-                kill_xp = kill_xp * (10000 + exp_bonus as u64) / 10000;
+/// Crit bonus in bps for one hit under the arena's crit model. `landed` is the
+/// hit's own roll; ExpectedValue ignores it and always adds the average.
+fn crit_bonus(rank: u8, model: CritModel, tables: &TalentTableValues, landed: bool) -> u32 {
+    if rank == 0 {
+        return 0;
+    }
+    let idx = rank.min(5) as usize - 1;
+    match model {
+        CritModel::ExpectedValue => tables.crit_expected_bps[idx],
+        CritModel::Real | CritModel::CappedBurst => {
+            if !landed {
+                return 0;
+            }
+            let bonus = tables.crit_mult_bps[idx].saturating_sub(10000);
+            if model == CritModel::CappedBurst {
+                bonus.min(CRIT_BURST_CAP_BPS)
+            } else {
+                bonus
             }
+        }
+    }
+}
+
+/// Per-hit damage with the value after every pipeline stage. `eff_max` is the
+/// attacker's and victim's effective max HP, passed in so a caller resolving
+/// several hits between the same pair computes them once. Talent percentages
+/// come from `tables` (the arena's TalentTables override or the compiled ones).
+/// `crit` is whether this hit's rolled crit landed.
+/// All math uses u32 with 10000 basis-point scaling to avoid floats.
+fn hit_damage_breakdown(
+    attacker: &PlayerState,
+    victim: &PlayerState,
+    config: &ArenaConfig,
+    tables: &TalentTableValues,
+    eff_max: (u32, u32),
+    now: i64,
+    crit_landed: bool,
+) -> DamageBreakdown {
+    let (attacker_eff_max, victim_eff_max) = eff_max;
+    // Every flat amount below is expressed in the arena's damage scale
+    let scale = config.damage_scale as u32;
+    let to_scale = |v: u32| v * scale / DAMAGE_SCALE;
+    // Stat lane: optional flat damage from attack level
+    let level_bonus = (attacker.attack_level.max(1) as u32 - 1) * config.attack_power_per_level as u32;
+    let base: u32 = to_scale(attacker.attack_power as u32 + level_bonus);
+    let cap = to_scale(DAMAGE_CAP);
 
-            attacker.xp += kill_xp;
-            arena.total_kills += 1;
+    // Heavy Hitter (slot 5 = talent_swift): +X% bullet damage
+    let heavy_hitter = lookup_bps(talent_rank(attacker.talent_swift, config), &tables.heavy_hitter_bps);
 
-            // Auto-level attacker after XP gain
-            let new_level = calc_level(attacker.xp);
-            attacker.health_level = new_level;
-            attacker.attack_level = new_level;
+    // Tree synergy: deep Firepower / Mass Damage investment
+    let synergy = tree_synergy_bps(attacker, config, TREE_FIREPOWER)
+        + tree_synergy_bps(attacker, config, TREE_MASS_DAMAGE);
 
-            msg!(
-                "KILL: {} -> {} | {} hits, {} dmg/hit, {} total | +{} XP | arena kills: {}",
-                attacker.wallet, victim.wallet,
-                hit_count, damage_per_hit, total_damage,
-                kill_xp, arena.total_kills
-            );
-        } else {
-            victim.health -= total_damage;
-            msg!(
-                "HIT: {} -> {} | {} hits, {} dmg/hit, {} total (hp: {}/{})",
-                attacker.wallet, victim.wallet,
-                hit_count, damage_per_hit, total_damage,
-                victim.health, victim.max_health
-            );
+    // Berserker (slot 24 = talent_frenzy): bonus damage below 33% HP, either flat
+    // or ramping toward 0 HP when the arena enables berserker_smooth
+    let mut berserker = 0;
+    if talent_rank(attacker.talent_frenzy, config) > 0 {
+        let threshold = attacker_eff_max * 3300 / 10000;
+        let health = attacker.health as u32;
+        if health <= threshold {
+            let full = lookup_bps(attacker.talent_frenzy, &tables.berserker_dmg_bps);
+            berserker = if config.berserker_smooth && threshold > 0 {
+                full * (threshold - health) / threshold
+            } else {
+                full
+            };
         }
-
-        Ok(())
     }
 
-    pub fn respawn_player(ctx: Context<RespawnPlayer>) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        require!(player.initialized, CombatError::NotInitialized);
-        require!(!player.is_alive, CombatError::AlreadyAlive);
+    // Vitality Strike (slot 4 = talent_armor): +X% of max HP as flat bonus damage
+    let mut vitality = 0;
+    if talent_rank(attacker.talent_armor, config) > 0 {
+        let vs = lookup_bps(attacker.talent_armor, &tables.vitality_strike_bps);
+        vitality = to_scale(attacker_eff_max) * vs / 10000;
+    }
 
-        let now = Clock::get()?.unix_timestamp;
-        require!(now >= player.respawn_at, CombatError::RespawnCooldown);
+    // Critical Strike (slot 7 = talent_evasion): the average DPS increase by
+    // default, or the hit's own roll under the arena's crit_model
+    let crit = crit_bonus(
+        talent_rank(attacker.talent_evasion, config),
+        config.crit_model,
+        tables,
+        crit_landed,
+    );
 
-        let eff_max = effective_max_health(player) as u16;
-        player.health = eff_max;
-        player.max_health = eff_max;
-        player.is_alive = true;
-        player.respawn_at = 0;
+    // Execute (slot 21 = talent_homing): +X% damage vs targets at or below the
+    // arena's execute threshold (50% HP by default)
+    let mut execute = 0;
+    if talent_rank(attacker.talent_homing, config) > 0 && in_execute_range(victim, victim_eff_max, config) {
+        execute = lookup_bps(attacker.talent_homing, &tables.execute_bps);
+    }
 
-        msg!("Player {} respawned (hp: {})", player.wallet, player.health);
-        Ok(())
+    // Revenge: bonus against whoever last killed the attacker
+    let mut revenge = 0;
+    if config.revenge_bps > 0 && attacker.last_killed_by == victim.wallet {
+        revenge = config.revenge_bps as u32;
     }
 
-    pub fn upgrade_stat(ctx: Context<UpgradeStat>, stat_type: u8) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        require!(player.initialized, CombatError::NotInitialized);
+    let mut b = DamageBreakdown { base, crit: crit_landed && crit > 0, ..Default::default() };
+    match config.damage_model {
+        // Each percentage bonus compounds on the previous one. The cap applies
+        // before Crit and Execute, so those two can push a hit past it.
+        DamageModel::Multiplicative => {
+            b.after_heavy_hitter = base * (10000 + heavy_hitter) / 10000;
+            b.after_synergy = b.after_heavy_hitter * (10000 + synergy) / 10000;
+            b.after_berserker = b.after_synergy * (10000 + berserker) / 10000;
+            b.after_revenge = b.after_berserker * (10000 + revenge) / 10000;
+            // Cap at 5.0 game-damage (DAMAGE_CAP, in the arena's scale)
+            b.after_vitality = (b.after_revenge + vitality).min(cap);
+            b.after_crit = b.after_vitality * (10000 + crit) / 10000;
+            b.after_execute = b.after_crit * (10000 + execute) / 10000;
+        }
+        // All percentage bonuses sum into one multiplier; the cap bounds the
+        // whole hit. Stages report the running sum.
+        DamageModel::Additive => {
+            let mut bonus = heavy_hitter;
+            b.after_heavy_hitter = base * (10000 + bonus) / 10000;
+            bonus += synergy;
+            b.after_synergy = base * (10000 + bonus) / 10000;
+            bonus += berserker;
+            b.after_berserker = base * (10000 + bonus) / 10000;
+            bonus += revenge;
+            b.after_revenge = base * (10000 + bonus) / 10000;
+            b.after_vitality = b.after_revenge + vitality;
+            bonus += crit;
+            b.after_crit = base * (10000 + bonus) / 10000 + vitality;
+            bonus += execute;
+            b.after_execute = (base * (10000 + bonus) / 10000 + vitality).min(cap);
+        }
+    }
+    // Dual capstone: build-diversity lever, a bonus or penalty for double-dipping
+    let dual = dual_capstone_bps(attacker, config);
+    if dual != 0 {
+        b.after_execute = (b.after_execute as i64 * (10000 + dual) as i64 / 10000) as u32;
+    }
 
-        let (current_level, label) = match stat_type {
-            0 => (player.health_level, "health"),
-            1 => (player.attack_level, "attack"),
-            _ => return Err(CombatError::InvalidStatType.into()),
-        };
+    // Offensive ceiling: however the bonuses stacked, one hit gets at most
+    // max_offensive_bps over its base (Vitality Strike's flat damage aside).
+    // Kill Rush only adds bullets server-side, so a low-HP Berserker on a kill
+    // streak is bounded here per hit.
+    if config.max_offensive_bps > 0 {
+        let ceiling = base * (10000 + config.max_offensive_bps as u32) / 10000 + vitality;
+        b.after_execute = b.after_execute.min(ceiling);
+    }
+    let mut dmg = b.after_execute;
 
-        require!(current_level < MAX_LEVEL, CombatError::MaxLevel);
+    // Armor (slot 0 = talent_iron_skin on victim): -X% incoming damage scaled by
+    // armor_scale_bps, less the attacker's flat armor penetration (the arena's
+    // plus any from the talent tables)
+    if talent_rank(victim.talent_iron_skin, config) > 0 {
+        let armor = (lookup_bps(victim.talent_iron_skin, &tables.armor_bps) * config.armor_scale_bps as u32 / 10000)
+            .saturating_sub(config.armor_pen_bps as u32 + tables.armor_pen_bps);
+        dmg = dmg * (10000 - armor.min(9999)) / 10000;
+    }
+    b.after_armor = dmg;
 
-        let cost = 100u64 + (current_level as u64) * 50;
-        require!(player.xp >= cost, CombatError::InsufficientXP);
+    // Arena-wide damage reduction, stacked multiplicatively on top of Armor
+    if config.global_damage_reduction_bps > 0 {
+        let reduction = (config.global_damage_reduction_bps as u32).min(10000);
+        dmg = dmg * (10000 - reduction) / 10000;
+    }
 
-        player.xp -= cost;
+    // Taunt: a taunted attacker deals less to anyone but their taunter
+    if config.taunt_reduction_bps > 0 && now < attacker.taunt_until && attacker.taunted_by != victim.wallet {
+        let reduction = (config.taunt_reduction_bps as u32).min(10000);
+        dmg = dmg * (10000 - reduction) / 10000;
+    }
 
-        match stat_type {
-            0 => {
-                player.health_level += 1;
-                player.max_health += 10;
-                if player.is_alive {
-                    player.health += 10;
-                }
-            }
-            1 => {
-                player.attack_level += 1;
-                player.attack_power += 5;
-            }
-            _ => unreachable!(),
-        }
+    b.final_damage = (dmg * DAMAGE_SCALE / scale).max(1) as u16;
+    b
+}
 
-        msg!("Player {} upgraded {} to level {}", player.wallet, label,
-            match stat_type { 0 => player.health_level, _ => player.attack_level });
-        Ok(())
+/// Integer square root (floor), Newton's method.
+fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
     }
+    x
+}
 
-    pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        require!(player.initialized, CombatError::NotInitialized);
+/// Hits that count toward a volley's damage. Up to `soft_hit_cap` every hit
+/// counts; past it the count grows as 2*sqrt(cap*hits) - cap, which keeps the
+/// slope at 1 where the cap starts and bends it down from there, so one
+/// misreported burst can't land its full linear damage.
+fn effective_hits(hit_count: u16, config: &ArenaConfig) -> u32 {
+    let cap = config.soft_hit_cap.max(1) as u64;
+    let hits = hit_count as u64;
+    if hits <= cap {
+        return hits as u32;
+    }
+    (2 * isqrt(cap * hits) - cap).min(hits) as u32
+}
 
-        player.health = BASE_HEALTH;
-        player.max_health = BASE_HEALTH;
-        player.attack_power = BASE_ATTACK;
-        player.xp = 0;
-        player.kills = 0;
-        player.deaths = 0;
-        player.health_level = 1;
-        player.attack_level = 1;
-        player.is_alive = true;
-        player.respawn_at = 0;
-        player.talent_iron_skin = 0;
-        player.talent_heavy_hitter = 0;
-        player.talent_regeneration = 0;
-        player.talent_lifesteal = 0;
-        player.talent_armor = 0;
-        player.talent_swift = 0;
-        player.talent_rapid_fire = 0;
-        player.talent_evasion = 0;
-        player.talent_quick_respawn = 0;
-        player.talent_momentum = 0;
-        player.talent_weakspot = 0;
-        player.talent_critical_strike = 0;
-        player.talent_focus_fire = 0;
-        player.talent_multi_shot = 0;
-        player.talent_dual_cannon = 0;
-        player.talent_deflect = 0;
-        player.talent_absorb = 0;
-        player.talent_last_stand = 0;
-        player.talent_cloak = 0;
-        player.talent_dash = 0;
-        player.talent_rampage = 0;
-        player.talent_homing = 0;
-        player.talent_ricochet = 0;
-        player.talent_deathbomb = 0;
-        player.talent_frenzy = 0;
-        player.manual_build = false;
-        msg!("Player {} reset to base stats", player.wallet);
-        Ok(())
+/// Breakdown of an ordinary hit of a `hit_count` volley, with the crit hits
+/// rolled per hit from `seed` and, if any landed, a crit hit's damage in
+/// `crit_damage`. The pipeline runs at most twice whatever the hit count.
+#[allow(clippy::too_many_arguments)]
+fn volley_breakdown(
+    attacker: &PlayerState,
+    victim: &PlayerState,
+    config: &ArenaConfig,
+    tables: &TalentTableValues,
+    eff_max: (u32, u32),
+    now: i64,
+    seed: u64,
+    hit_count: u16,
+) -> DamageBreakdown {
+    let crits = volley_crits(
+        talent_rank(attacker.talent_evasion, config),
+        config.crit_model,
+        &tables.crit_chance_bps,
+        seed,
+        hit_count,
+    );
+    let mut b = hit_damage_breakdown(attacker, victim, config, tables, eff_max, now, false);
+    if crits > 0 {
+        b.crit = true;
+        b.crit_hits = crits;
+        b.crit_damage = hit_damage_breakdown(attacker, victim, config, tables, eff_max, now, true).final_damage;
     }
+    b
+}
 
-    /// Allocate a talent point with full on-chain validation:
-    /// - Level-based point budget
-    /// - Max rank per talent
-    /// - Prerequisite chain (tier N requires tier N-1)
-    /// - Capstone limit (max 2 of 5 capstones)
-    pub fn allocate_talent(ctx: Context<AllocateTalent>, talent_id: u8) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        require!(player.initialized, CombatError::NotInitialized);
-        require!(talent_id <= 24, CombatError::InvalidTalentId);
+/// Raw sum of the first `hits` hits of a volley, crit hits first.
+fn volley_sum(b: &DamageBreakdown, hits: u64) -> u64 {
+    let crits = (b.crit_hits as u64).min(hits);
+    b.final_damage as u64 * (hits - crits) + b.crit_damage as u64 * crits
+}
 
-        let level = calc_level(player.xp);
-        let total_points = calc_talent_points(level);
-        let spent = player.total_talent_points_spent();
-        require!(spent < total_points, CombatError::NoTalentPoints);
+/// Volley total: every hit as `volley_breakdown` resolved it, scaled down to
+/// the effective hit count past the soft cap, then by difficulty.
+fn calc_volley_damage(b: &DamageBreakdown, hit_count: u16, config: &ArenaConfig) -> u16 {
+    let hits = hit_count.max(1) as u64;
+    let total = volley_sum(b, hits) * effective_hits(hit_count, config) as u64 / hits;
+    // Difficulty scales the whole volley after every talent, armor and floor
+    let total = total * config.difficulty_bps as u64 / 10000;
+    total.min(u16::MAX as u64) as u16
+}
 
-        let current = player.get_talent(talent_id);
-        require!(current < max_rank_for_talent(talent_id), CombatError::TalentMaxed);
 
-        // Prerequisite: each talent requires at least 1 rank in the previous talent of its tree
-        let prereq = talent_prerequisite(talent_id);
-        if let Some(prereq_id) = prereq {
-            require!(player.get_talent(prereq_id) >= 1, CombatError::PrerequisiteNotMet);
+/// Clamp a volley against the victim's per-life damage budget. A lethal volley
+/// is never cut below the victim's health, so the cap only trims overkill.
+fn clamp_life_damage(victim: &PlayerState, total_damage: u16, victim_eff_max: u32) -> u16 {
+    let budget = (victim_eff_max * LIFE_DAMAGE_CAP_BPS / 10000).min(u16::MAX as u32) as u16;
+    let remaining = budget.saturating_sub(victim.damage_taken_this_life);
+    total_damage.min(remaining.max(victim.health))
+}
+
+/// A victim `process_attack` may resolve a hit against. `is_alive` alone is the
+/// rule; the tombstone makes a second death in the same racing batch impossible
+/// even if the flag were ever out of step.
+fn can_be_hit(victim: &PlayerState, now: i64) -> bool {
+    victim.is_alive && now >= victim.death_grace_until
+}
+
+/// Victim side of a death: HP, alive count, streak, death XP and ghost timer.
+/// `killer` is the player credited with it, or the default key for the zone.
+/// Returns the victim's pre-death level.
+fn record_death(victim: &mut PlayerState, arena: &mut Arena, killer: Pubkey, now: i64) -> u8 {
+    let victim_level = calc_level(victim.xp);
+    victim.health = 0;
+    victim.is_alive = false;
+    victim.deaths += 1;
+    arena.alive_count = arena.alive_count.saturating_sub(1);
+    let grace_ready = victim.streak_grace_used_at == 0
+        || now - victim.streak_grace_used_at >= STREAK_GRACE_COOLDOWN_SECS;
+    if arena.config.streak_grace && victim.current_streak >= 2 && grace_ready {
+        victim.current_streak /= 2;
+        victim.streak_grace_used_at = now;
+    } else {
+        victim.current_streak = 0;
+    }
+    victim.last_killed_by = killer;
+    if arena.config.death_xp_enabled {
+        victim.xp += scale_xp(XP_PER_DEATH, &arena.config);
+    }
+    victim.respawn_at = now + calc_ghost_secs(victim_level);
+    victim.death_grace_until = now + DEATH_GRACE_SECS;
+    victim.respawn_pending = true;
+    emit!(RespawnReadyEvent {
+        wallet: victim.wallet,
+        respawn_at: victim.respawn_at,
+    });
+    victim_level
+}
+
+/// Every death an attacker causes goes through here, whether it is the primary
+/// target or an incidental AoE kill, so the bookkeeping cannot drift:
+/// - the victim gets `record_death` (deaths, death XP, ghost timer,
+///   `last_killed_by` = attacker, alive count);
+/// - the attacker gets the kill: `kills`, streak, unique killer, revenge, and
+///   the arena's `total_kills`.
+///
+/// Kill XP is the caller's job: only the primary target pays it. Returns the
+/// victim's pre-death level.
+fn apply_death(victim: &mut PlayerState, attacker: &mut PlayerState, arena: &mut Arena, now: i64) -> u8 {
+    let victim_level = record_death(victim, arena, attacker.wallet, now);
+    attacker.kills += 1;
+    arena.total_kills += 1;
+    if attacker.kill_session != arena.session_id {
+        attacker.kill_session = arena.session_id;
+        arena.unique_killers = arena.unique_killers.saturating_add(1);
+    }
+    attacker.current_streak = attacker.current_streak.saturating_add(1);
+    if attacker.last_killed_by == victim.wallet {
+        attacker.last_killed_by = Pubkey::default();
+        if arena.config.revenge_bps > 0 {
+            emit!(RevengeEvent {
+                avenger: attacker.wallet,
+                victim: victim.wallet,
+            });
         }
+    }
+    victim_level
+}
+
+/// Pay out the arena bounty if `victim` carries it. The bounty clears, so it
+/// pays exactly once. Returns the XP owed to the killer (0 without a bounty).
+fn claim_bounty(arena: &mut Arena, victim: Pubkey) -> u64 {
+    if arena.bounty_target == Pubkey::default() || arena.bounty_target != victim {
+        return 0;
+    }
+    let xp = arena.bounty_xp;
+    clear_bounty(arena);
+    xp
+}
+
+fn clear_bounty(arena: &mut Arena) {
+    arena.bounty_target = Pubkey::default();
+    arena.bounty_xp = 0;
+}
+
+/// Zone damage owed by a player outside the safe zone since their last tick
+/// (or the shrink start), ramping with the shrink's age and capped per tick.
+fn zone_damage(player: &PlayerState, zone: &ZoneState, eff_max: u32, now: i64) -> u16 {
+    if zone.shrink_started_at == 0 || zone.damage_per_sec == 0 || player.in_zone {
+        return 0;
+    }
+    let since = player.last_zone_tick_at.max(zone.shrink_started_at);
+    if now <= since {
+        return 0;
+    }
+    let ramp = 1 + (now - zone.shrink_started_at) / ZONE_RAMP_SECS;
+    let raw = (zone.damage_per_sec as i64).saturating_mul(ramp).saturating_mul(now - since);
+    let cap = (eff_max * ZONE_MAX_TICK_BPS / 10000).max(1) as i64;
+    raw.min(cap) as u16
+}
+
+/// Push `victim` into the attacker's anti-farm ring buffer and return how many
+/// times it already appeared there. The buffer is wiped once the attacker has
+/// gone ANTI_FARM_WINDOW_SECS without a kill.
+fn record_victim(attacker: &mut PlayerState, victim: Pubkey, now: i64) -> usize {
+    if now - attacker.last_kill_at > ANTI_FARM_WINDOW_SECS {
+        attacker.recent_victims = [Pubkey::default(); ANTI_FARM_SLOTS];
+        attacker.recent_victims_head = 0;
+    }
+    let repeats = attacker.recent_victims.iter().filter(|&&v| v == victim).count();
+    let head = attacker.recent_victims_head as usize % ANTI_FARM_SLOTS;
+    attacker.recent_victims[head] = victim;
+    attacker.recent_victims_head = ((head + 1) % ANTI_FARM_SLOTS) as u8;
+    attacker.last_kill_at = now;
+    repeats
+}
+
+/// Apply the arena's event multiplier (e.g. double XP) to an XP gain.
+fn scale_xp(xp: u64, config: &ArenaConfig) -> u64 {
+    (xp as u128 * config.xp_multiplier_bps as u128 / 10000).min(u64::MAX as u128) as u64
+}
 
-        // Capstone limit: max 2 of the 5 capstone talents (slots 4, 9, 14, 17, 24)
-        let capstones: [u8; 5] = [4, 9, 14, 17, 24];
-        if capstones.contains(&talent_id) && current == 0 {
-            let chosen = capstones.iter()
-                .filter(|&&id| player.get_talent(id) > 0)
-                .count();
-            require!(chosen < 2, CombatError::MaxCapstones);
+/// Pay the control point holder for the time held since their last claim,
+/// bounded by POINT_MAX_ACCRUAL_SECS. Point XP goes through the arena's XP
+/// multiplier and auto-leveling like every other XP gain. Returns the XP paid
+/// and the seconds it covered.
+fn claim_point(player: &mut PlayerState, point: &mut ControlPoint, now: i64, config: &ArenaConfig) -> (u64, i64) {
+    let elapsed = now.saturating_sub(point.last_claim_at).clamp(0, POINT_MAX_ACCRUAL_SECS);
+    let xp = scale_xp(elapsed as u64 * POINT_XP_PER_SEC, config);
+    point.last_claim_at = now;
+    player.xp = player.xp.saturating_add(xp);
+    auto_level(player, config);
+    (xp, elapsed)
+}
+
+/// XP the attacker earns for killing a victim who was at `victim_level`.
+fn calc_kill_xp(attacker: &PlayerState, victim_level: u64, config: &ArenaConfig) -> u64 {
+    // XP scales with victim level along the arena's curve
+    let steps = victim_level.saturating_sub(1);
+    let scaled_steps = match config.kill_xp_curve {
+        KillXpCurve::Linear => steps,
+        KillXpCurve::Sqrt => isqrt(steps * KILL_XP_SQRT_PIVOT),
+        KillXpCurve::Capped => steps.min(KILL_XP_CAP_LEVEL - 1),
+    };
+    let mut kill_xp = XP_PER_KILL_BASE + scaled_steps * XP_PER_KILL_PER_LEVEL;
+
+    // Bounty: 2x XP for killing level 50+ players
+    if victim_level >= 50 {
+        kill_xp *= 2;
+    }
+
+    // Experience talent (slot 20 = talent_rampage): +X% XP
+    let exp_bonus = lookup_bps(talent_rank(attacker.talent_rampage, config), &EXPERIENCE_BPS);
+    if exp_bonus > 0 {
+        kill_xp = kill_xp * (10000 + exp_bonus as u64) / 10000;
+    }
+
+    // Tree synergy: deep Blood investment
+    let synergy = tree_synergy_bps(attacker, config, TREE_BLOOD);
+    if synergy > 0 {
+        kill_xp = kill_xp * (10000 + synergy as u64) / 10000;
+    }
+
+    kill_xp
+}
+
+/// Share of kill XP (bps) left after anti-stomp: full up to `anti_stomp_gap`
+/// levels in the attacker's favor, then ANTI_STOMP_BPS_PER_LEVEL less for each
+/// level beyond, down to zero.
+fn anti_stomp_bps(attacker_level: u8, victim_level: u8, config: &ArenaConfig) -> u64 {
+    if !config.anti_stomp {
+        return 10000;
+    }
+    let excess = attacker_level.saturating_sub(victim_level).saturating_sub(config.anti_stomp_gap) as u64;
+    10000u64.saturating_sub(excess * ANTI_STOMP_BPS_PER_LEVEL)
+}
+
+/// Comeback max-HP bonus (bps) for a player at `level` when the arena's top
+/// player is at `top_level`: COMEBACK_BPS_PER_LEVEL per level of the gap beyond
+/// `comeback_level_gap`, up to COMEBACK_MAX_BPS.
+fn comeback_bps(level: u8, top_level: u8, config: &ArenaConfig) -> u32 {
+    let excess = top_level.saturating_sub(level).saturating_sub(config.comeback_level_gap) as u32;
+    (excess * COMEBACK_BPS_PER_LEVEL).min(COMEBACK_MAX_BPS)
+}
+
+/// Schema version of a stored player account, `None` for the untagged layouts
+/// from before `schema_version`, which are told apart by length alone. Rejects
+/// data that is not a PlayerState, is larger than the current layout or
+/// carries an unknown version.
+fn player_schema_version(data: &[u8]) -> Result<Option<u8>> {
+    require!(data.len() <= PLAYER_STATE_SPACE, CombatError::MigrationAccountTooLarge);
+    let expected_disc: [u8; 8] = [56, 3, 60, 86, 174, 16, 244, 195];
+    require!(
+        data.len() >= 8 && data[..8] == expected_disc,
+        CombatError::MigrationBadDiscriminator
+    );
+    if data.len() < FIRST_TAGGED_PLAYER_SPACE {
+        return Ok(None);
+    }
+    let v = data[data.len() - 1];
+    require!(v > 0 && v <= PLAYER_SCHEMA_VERSION, CombatError::InvalidMigration);
+    Ok(Some(v))
+}
+
+/// Move cooldowns still held in the per-ability fields that `ability_cooldowns`
+/// replaced into their slots, so a migration doesn't reset a running cooldown.
+/// The old fields are zeroed afterwards, which makes this safe to repeat.
+fn carry_legacy_cooldowns(player: &mut PlayerState) {
+    let legacy = [
+        (ABILITY_ULT, player.ult_ready_at),
+        (ABILITY_DASH, player.dash_ready_at),
+        (ABILITY_HEAL, player.heal_ready_at),
+    ];
+    for (idx, ready_at) in legacy {
+        player.ability_cooldowns[idx] = player.ability_cooldowns[idx].max(ready_at);
+    }
+    player.ult_ready_at = 0;
+    player.dash_ready_at = 0;
+    player.heal_ready_at = 0;
+}
+
+/// Scale a per-hit damage by the arena's falloff for `distance_bucket`. A hit
+/// that dealt damage still deals at least 1 after falloff. This is where
+/// `distance_bucket` is validated.
+fn apply_falloff(damage_per_hit: u16, distance_bucket: u8, config: &ArenaConfig) -> Result<u16> {
+    require!((distance_bucket as usize) < FALLOFF_BUCKETS, CombatError::InvalidDistanceBucket);
+    if damage_per_hit == 0 {
+        return Ok(0);
+    }
+    let bps = config.falloff_table[distance_bucket as usize] as u32;
+    Ok(((damage_per_hit as u32 * bps / 10000) as u16).max(1))
+}
+
+/// Heal the attacker by Lifesteal's share of `damage_dealt`, up to the heal
+/// ceiling. Returns the HP actually restored.
+fn apply_lifesteal(
+    attacker: &mut PlayerState,
+    damage_dealt: u16,
+    attacker_eff_max: u32,
+    config: &ArenaConfig,
+) -> u16 {
+    let bps = lookup_bps(talent_rank(attacker.talent_lifesteal, config), &LIFESTEAL_BPS);
+    if bps == 0 {
+        return 0;
+    }
+    let ceiling = attacker_eff_max * LIFESTEAL_HEAL_CEILING_BPS / 10000;
+    let current = attacker.health as u32;
+    if current >= ceiling {
+        return 0;
+    }
+    let heal = (damage_dealt as u32 * bps / 10000).min(ceiling - current);
+    attacker.health = (current + heal) as u16;
+    heal as u16
+}
+
+/// Hit the attacker with the victim's Counter Attack. Counter damage is never
+/// lethal (floors at 1 HP) so kills only ever come from the primary attack.
+/// Returns the HP actually removed.
+#[allow(clippy::too_many_arguments)]
+fn apply_counter(
+    attacker: &mut PlayerState,
+    victim: &PlayerState,
+    hit_count: u16,
+    config: &ArenaConfig,
+    tables: &TalentTableValues,
+    eff_max: (u32, u32),
+    now: i64,
+    slot: u64,
+) -> u16 {
+    let chance = lookup_bps(talent_rank(victim.talent_absorb, config), &COUNTER_CHANCE_BPS);
+    if chance == 0 || !victim.is_alive {
+        return 0;
+    }
+    // Roles swap: the victim fires back at the attacker
+    let seed = crit_seed(victim, attacker, now, slot);
+    let b = volley_breakdown(victim, attacker, config, tables, (eff_max.1, eff_max.0), now, seed, hit_count);
+    // Past the soft cap the counter thins out like the volley it answers
+    let hits = hit_count.max(1) as u64;
+    let volley = volley_sum(&b, hits) * effective_hits(hit_count, config) as u64 / hits;
+    let counter = (volley * chance as u64 / 10000).min(u16::MAX as u64) as u16;
+    let before = attacker.health;
+    attacker.health = before.saturating_sub(counter).max(1);
+    before - attacker.health
+}
+
+/// Resolve Lifesteal and Counter Attack after the primary hit, in the order the
+/// arena's `combat_order` selects. `eff_max` is the attacker's and victim's
+/// effective max HP. Returns `(healed, countered)`.
+#[allow(clippy::too_many_arguments)]
+fn apply_sustain(
+    attacker: &mut PlayerState,
+    victim: &PlayerState,
+    damage_dealt: u16,
+    hit_count: u16,
+    config: &ArenaConfig,
+    tables: &TalentTableValues,
+    eff_max: (u32, u32),
+    now: i64,
+    slot: u64,
+) -> (u16, u16) {
+    match config.combat_order {
+        // Heal first: lifesteal is capped by the ceiling at full-ish HP, then the
+        // counter lands on the healed pool.
+        CombatOrder::LifestealBeforeCounter => {
+            let healed = apply_lifesteal(attacker, damage_dealt, eff_max.0, config);
+            let countered = apply_counter(attacker, victim, hit_count, config, tables, eff_max, now, slot);
+            (healed, countered)
         }
+        // Counter first: the attacker takes the return fire, then lifesteal can
+        // refill the HP the counter removed.
+        CombatOrder::CounterBeforeLifesteal => {
+            let countered = apply_counter(attacker, victim, hit_count, config, tables, eff_max, now, slot);
+            let healed = apply_lifesteal(attacker, damage_dealt, eff_max.0, config);
+            (healed, countered)
+        }
+    }
+}
 
-        player.set_talent(talent_id, current + 1);
-        player.manual_build = true;
+/// Out-of-combat regen owed to `player` since its last tick, in HP. The base
+/// rate tops up whatever the Regeneration talent already heals below its
+/// ceiling, so the combined rate is max(base, talent) rather than the sum.
+fn out_of_combat_regen(player: &PlayerState, config: &ArenaConfig, eff_max: u32, now: i64) -> u32 {
+    let start = player.last_regen_at.max(player.last_combat_at + config.out_of_combat_secs as i64);
+    if config.out_of_combat_regen_per_sec == 0 || now <= start {
+        return 0;
+    }
+    let current = player.health as u32;
+    let base_tenths = config.out_of_combat_regen_per_sec as u32 * 10;
+    let talent_tenths = talent_rank(player.talent_regeneration, config).min(5) as u32 * REGEN_TENTHS_PER_RANK;
+    let tenths = if current < eff_max * REGEN_HEAL_CEILING_BPS / 10000 {
+        base_tenths.saturating_sub(talent_tenths)
+    } else {
+        base_tenths
+    };
+    let elapsed = (now - start).min(u32::MAX as i64) as u64;
+    let heal = (elapsed * tenths as u64 / 10).min(u32::MAX as u64) as u32;
+    heal.min(eff_max.saturating_sub(current))
+}
 
-        msg!("Player {} allocated talent {} to rank {}", player.wallet, talent_id, current + 1);
+/// Leaderboard sort key. Players rank by, in order:
+///   1. xp      (higher first)
+///   2. kills   (higher first)
+///   3. deaths  (lower first)
+///   4. wallet  (lower bytes first)
+///
+/// Encoded big-endian so that comparing keys byte-wise, descending, gives the
+/// ranking: deaths are stored as `u64::MAX - deaths` and the wallet bytes are
+/// bit-inverted so the "lower first" fields also sort descending.
+fn rank_key(player: &PlayerState) -> [u8; 56] {
+    let mut key = [0u8; 56];
+    key[0..8].copy_from_slice(&player.xp.to_be_bytes());
+    key[8..16].copy_from_slice(&player.kills.to_be_bytes());
+    key[16..24].copy_from_slice(&(u64::MAX - player.deaths).to_be_bytes());
+    for (dst, src) in key[24..56].iter_mut().zip(player.wallet.to_bytes()) {
+        *dst = !src;
+    }
+    key
+}
+
+/// Private arenas only take wallets on their allowlist; public arenas ignore it.
+fn require_allowlisted(arena: &Arena, allowlist: Option<&Allowlist>, wallet: &Pubkey) -> Result<()> {
+    if !arena.config.private {
+        return Ok(());
+    }
+    let listed = allowlist.is_some_and(|list| list.wallets.contains(wallet));
+    if !listed {
+        msg!("Wallet {} is not allowlisted", wallet);
+    }
+    require!(listed, CombatError::NotAllowlisted);
+    Ok(())
+}
+
+/// Move the arena to `phase`, announcing the change. Setting the current phase is a no-op.
+fn set_phase(arena: &mut Account<Arena>, phase: u8) -> Result<()> {
+    if arena.phase == phase {
+        return Ok(());
+    }
+    emit!(PhaseChangedEvent {
+        arena: arena.key(),
+        from: arena.phase,
+        to: phase,
+        timestamp: checked_now()?,
+    });
+    arena.phase = phase;
+    Ok(())
+}
+
+/// Open the arena's next session: every player can count as a new killer again.
+fn next_session(arena: &mut Arena) {
+    arena.session_id = arena.session_id.wrapping_add(1).max(1);
+    arena.unique_killers = 0;
+}
+
+/// Whether `a` and `b` may damage each other: duelists only each other, and
+/// players outside a duel only other players outside one.
+fn can_fight(a: &PlayerState, b: &PlayerState) -> bool {
+    a.duel == b.duel
+}
+
+/// Count one gameplay state change toward the arena's `event_seq`.
+fn bump_event_seq(arena: &mut Arena) {
+    arena.event_seq = arena.event_seq.wrapping_add(1);
+}
+
+/// Return data for a commit of `accounts` accounts at the current clock.
+fn commit_receipt(arena: &Arena, accounts: usize) -> Result<CommitReceipt> {
+    Ok(CommitReceipt {
+        committed_at: checked_now()?,
+        event_seq: arena.event_seq,
+        accounts: accounts as u32,
+    })
+}
+
+/// Record an arena commit for `next_commit_recommended_at`. Callers must still
+/// `exit` the arena before handing it to the commit CPI.
+fn mark_committed(arena: &mut Arena) -> Result<()> {
+    arena.last_commit_at = checked_now()?;
+    arena.dirty_since_commit = 0;
+    Ok(())
+}
+
+/// Commit instructions pass `remaining_accounts` straight to the magic program.
+/// Reject anything that isn't one of our own delegated state accounts up front so
+/// a malformed call fails with a clear error instead of deep inside the CPI.
+fn require_committable(accounts: &[AccountInfo]) -> Result<()> {
+    for (i, acct) in accounts.iter().enumerate() {
+        let valid = acct.owner == &crate::ID && {
+            let data = acct.try_borrow_data()?;
+            data.len() >= 8
+                && (data[..8] == *PlayerState::DISCRIMINATOR
+                    || data[..8] == *Arena::DISCRIMINATOR
+                    || data[..8] == *ControlPoint::DISCRIMINATOR
+                    || data[..8] == *Duel::DISCRIMINATOR)
+        };
+        if !valid {
+            msg!("Invalid commit account at remaining_accounts[{}]: {}", i, acct.key());
+            return err!(CombatError::InvalidCommitAccount);
+        }
+    }
+    Ok(())
+}
+
+#[ephemeral]
+#[program]
+pub mod hodlwarz_combat {
+    use super::*;
+
+    pub fn init_arena(ctx: Context<InitArena>, arena_id: u16) -> Result<()> {
+        let arena = &mut ctx.accounts.arena;
+        arena.authority = ctx.accounts.authority.key();
+        arena.player_count = 0;
+        arena.total_kills = 0;
+        arena.is_active = true;
+        arena.config = ArenaConfig::default();
+        arena.winner = None;
+        arena.arena_id = arena_id;
+        arena.commit_chunk_next = 0;
+        arena.commit_chunk_total = 0;
+        arena.total_damage_dealt = 0;
+        arena.alive_count = 0;
+        arena.unique_killers = 0;
+        arena.zone = ZoneState::default();
+        arena.last_commit_at = 0;
+        arena.dirty_since_commit = 0;
+        arena.phase = PHASE_LIVE;
+        arena.bounty_target = Pubkey::default();
+        arena.bounty_xp = 0;
+        arena.session_id = 1;
+        arena.event_seq = 0;
+        emit!(ArenaInitializedEvent {
+            arena: arena.key(),
+            authority: arena.authority,
+            timestamp: checked_now()?,
+        });
+        msg!("Arena {} initialized by {}", arena_id, arena.authority);
         Ok(())
     }
 
-    pub fn reset_talents(ctx: Context<ResetTalents>) -> Result<()> {
-        let player = &mut ctx.accounts.player_state;
-        require!(player.initialized, CombatError::NotInitialized);
+    pub fn set_arena_config(ctx: Context<SetArenaConfig>, config: ArenaConfig) -> Result<()> {
+        config.validate()?;
+        ctx.accounts.arena.config = config;
+        msg!("Arena config updated by {}", ctx.accounts.authority.key());
+        Ok(())
+    }
 
-        player.talent_iron_skin = 0;
-        player.talent_heavy_hitter = 0;
-        player.talent_regeneration = 0;
-        player.talent_lifesteal = 0;
-        player.talent_armor = 0;
-        player.talent_swift = 0;
-        player.talent_rapid_fire = 0;
-        player.talent_evasion = 0;
-        player.talent_quick_respawn = 0;
-        player.talent_momentum = 0;
-        player.talent_weakspot = 0;
-        player.talent_critical_strike = 0;
-        player.talent_focus_fire = 0;
-        player.talent_multi_shot = 0;
-        player.talent_dual_cannon = 0;
-        player.talent_deflect = 0;
-        player.talent_absorb = 0;
-        player.talent_last_stand = 0;
-        player.talent_cloak = 0;
-        player.talent_dash = 0;
-        player.talent_rampage = 0;
-        player.talent_homing = 0;
-        player.talent_ricochet = 0;
-        player.talent_deathbomb = 0;
-        player.talent_frenzy = 0;
-        player.manual_build = true;
-        msg!("Player {} talents reset", player.wallet);
+    /// Stop attacks in the arena until `resume_arena`.
+    pub fn pause_arena(ctx: Context<SetArenaActive>) -> Result<()> {
+        let arena = &mut ctx.accounts.arena;
+        arena.is_active = false;
+        emit!(ArenaPausedEvent {
+            arena: arena.key(),
+            authority: arena.authority,
+            timestamp: checked_now()?,
+        });
+        msg!("Arena paused by {}", arena.authority);
         Ok(())
     }
 
-    pub fn migrate_player(ctx: Context<MigratePlayer>) -> Result<()> {
-        let player_info = &ctx.accounts.player_state;
-        let current_len = player_info.data_len();
-        let target_len: usize = 8 + 32 + 2 + 2 + 2 + 8 + 8 + 8 + 1 + 1 + 1 + 8 + 1 + 25 + 1;
+    pub fn resume_arena(ctx: Context<SetArenaActive>) -> Result<()> {
+        let arena = &mut ctx.accounts.arena;
+        arena.is_active = true;
+        emit!(ArenaResumedEvent {
+            arena: arena.key(),
+            authority: arena.authority,
+            timestamp: checked_now()?,
+        });
+        msg!("Arena resumed by {}", arena.authority);
+        Ok(())
+    }
+
+    /// Put `bounty_xp` on `target`'s head; the next player to kill them collects
+    /// it. Replaces any open bounty, and the default pubkey clears the board.
+    pub fn place_bounty(ctx: Context<SetArenaActive>, target: Pubkey, bounty_xp: u64) -> Result<()> {
+        let arena = &mut ctx.accounts.arena;
+        if target == Pubkey::default() {
+            clear_bounty(arena);
+            msg!("Bounty cleared by {}", arena.authority);
+            return Ok(());
+        }
+        require!(bounty_xp > 0, CombatError::InvalidConfig);
+        arena.bounty_target = target;
+        arena.bounty_xp = bounty_xp;
+        msg!("Bounty of {} XP placed on {}", bounty_xp, target);
+        Ok(())
+    }
+
+    /// Pre-match staging: players can register, delegate, respawn and level,
+    /// but attacks deal no damage until `start_match`.
+    pub fn start_warmup(ctx: Context<SetArenaActive>) -> Result<()> {
+        set_phase(&mut ctx.accounts.arena, PHASE_WARMUP)?;
+        msg!("Arena warmup started by {}", ctx.accounts.authority.key());
+        Ok(())
+    }
+
+    /// End warmup and let attacks land.
+    pub fn start_match(ctx: Context<SetArenaActive>) -> Result<()> {
+        require!(ctx.accounts.arena.phase == PHASE_WARMUP, CombatError::WrongPhase);
+        set_phase(&mut ctx.accounts.arena, PHASE_LIVE)?;
+        msg!("Match started by {}", ctx.accounts.authority.key());
+        Ok(())
+    }
+
+    /// Rebuild `alive_count` from the player accounts in `remaining_accounts`.
+    /// Migrated arenas start the count at 0. Large arenas can recount over
+    /// several transactions: the first passes `reset` to zero the count, the
+    /// rest add to it. Returns the arena's count afterwards.
+    pub fn recount_alive<'a>(ctx: Context<'_, '_, 'a, 'a, ForceResetArena<'a>>, reset: bool) -> Result<u32> {
+        let arena = &mut ctx.accounts.arena;
+        if reset {
+            arena.alive_count = 0;
+        }
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut counted: u32 = 0;
+        for acct in ctx.remaining_accounts.iter() {
+            if seen.contains(acct.key) {
+                continue;
+            }
+            seen.push(*acct.key);
+            let player: Account<'a, PlayerState> = Account::try_from(acct)?;
+            if !player.initialized {
+                continue;
+            }
+            require_keys_eq!(player.arena, arena.key(), CombatError::WrongArena);
+            if player.is_alive {
+                counted += 1;
+            }
+        }
+        arena.alive_count = arena.alive_count.saturating_add(counted);
+        msg!("Recounted {} alive of {} listed (arena alive: {})", counted, seen.len(), arena.alive_count);
+        Ok(arena.alive_count)
+    }
+
+    /// Restart a stuck round: clears the kill count, winner and session state
+    /// and reactivates the arena. Up to MAX_BATCH_RESET player accounts in
+    /// `remaining_accounts` are reset to base stats as well. Delegation is left
+    /// as is, so this runs the same on the ER and on base layer.
+    pub fn force_reset_arena<'a>(ctx: Context<'_, '_, 'a, 'a, ForceResetArena<'a>>) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BATCH_RESET,
+            CombatError::InvalidBatchSize
+        );
+        let arena = &mut ctx.accounts.arena;
+
+        let mut players_reset: u8 = 0;
+        for acct in ctx.remaining_accounts.iter() {
+            let mut player: Account<'a, PlayerState> = Account::try_from(acct)?;
+            if !player.initialized {
+                continue;
+            }
+            require_keys_eq!(player.arena, arena.key(), CombatError::WrongArena);
+            if !player.is_alive {
+                arena.alive_count = arena.alive_count.saturating_add(1);
+            }
+            reset_to_base(&mut player);
+            apply_start_profile(&mut player, &arena.config);
+            player.exit(&crate::ID)?;
+            players_reset += 1;
+        }
+
+        arena.total_kills = 0;
+        arena.winner = None;
+        arena.commit_chunk_next = 0;
+        arena.commit_chunk_total = 0;
+        next_session(arena);
+        arena.zone = ZoneState::default();
+        arena.is_active = true;
+        arena.phase = PHASE_LIVE;
+        clear_bounty(arena);
+
+        emit!(ArenaResetEvent {
+            arena: arena.key(),
+            authority: arena.authority,
+            players_reset,
+            timestamp: checked_now()?,
+        });
+        msg!("Arena force-reset by {} ({} players reset)", arena.authority, players_reset);
+        Ok(())
+    }
+
+    /// Grow a pre-config arena to the current layout. The header fields
+    /// (authority, counters, is_active) are carried over; the config is reset to
+    /// defaults and can be re-applied with `set_arena_config`. The legacy
+    /// singleton arena migrates as `arena_id` 0.
+    pub fn migrate_arena(ctx: Context<MigrateArena>, arena_id: u16) -> Result<()> {
+        let arena_info = &ctx.accounts.arena;
+        let current_len = arena_info.data_len();
 
-        if current_len == target_len {
-            msg!("Account already at target size, no migration needed");
+        if current_len == ARENA_SPACE {
+            msg!("Arena already at target size, no migration needed");
             return Ok(());
         }
 
-        require!(current_len < target_len, CombatError::InvalidMigration);
+        require!(current_len < ARENA_SPACE, CombatError::InvalidMigration);
 
-        let data = player_info.try_borrow_data()?;
-        let expected_disc: [u8; 8] = [56, 3, 60, 86, 174, 16, 244, 195];
-        require!(data[..8] == expected_disc, CombatError::InvalidMigration);
+        let data = arena_info.try_borrow_data()?;
+        require!(data[..8] == *Arena::DISCRIMINATOR, CombatError::InvalidMigration);
+        let header = ArenaHeader::deserialize(&mut &data[8..])?;
         drop(data);
+        require_keys_eq!(header.authority, ctx.accounts.authority.key(), CombatError::Unauthorized);
 
         let rent = Rent::get()?;
-        let new_min = rent.minimum_balance(target_len);
-        let old_balance = player_info.lamports();
+        let new_min = rent.minimum_balance(ARENA_SPACE);
+        let old_balance = arena_info.lamports();
         if new_min > old_balance {
             let diff = new_min - old_balance;
             anchor_lang::system_program::transfer(
@@ -552,7 +1740,7 @@ pub mod hodlwarz_combat {
                     ctx.accounts.system_program.to_account_info(),
                     anchor_lang::system_program::Transfer {
                         from: ctx.accounts.authority.to_account_info(),
-                        to: player_info.to_account_info(),
+                        to: arena_info.to_account_info(),
                     },
                 ),
                 diff,
@@ -560,253 +1748,3014 @@ pub mod hodlwarz_combat {
         }
 
         #[allow(deprecated)]
-        player_info.realloc(target_len, false)?;
+        arena_info.realloc(ARENA_SPACE, false)?;
+
+        let arena = Arena {
+            authority: header.authority,
+            player_count: header.player_count,
+            total_kills: header.total_kills,
+            is_active: header.is_active,
+            config: ArenaConfig::default(),
+            winner: None,
+            arena_id,
+            commit_chunk_next: 0,
+            commit_chunk_total: 0,
+            total_damage_dealt: 0,
+            alive_count: 0,
+            unique_killers: 0,
+            zone: ZoneState::default(),
+            last_commit_at: 0,
+            dirty_since_commit: 0,
+            phase: PHASE_LIVE,
+            bounty_target: Pubkey::default(),
+            bounty_xp: 0,
+            session_id: 1,
+            event_seq: 0,
+        };
+        arena.try_serialize(&mut &mut arena_info.try_borrow_mut_data()?[..])?;
 
-        msg!("Player account migrated from {} to {} bytes", current_len, target_len);
+        msg!("Arena migrated from {} to {} bytes", current_len, ARENA_SPACE);
         Ok(())
     }
 
-    pub fn commit_state<'a>(ctx: Context<'_, '_, 'a, 'a, CommitState<'a>>) -> Result<()> {
-        let arena_info = ctx.accounts.arena.to_account_info();
-        let mut to_commit: Vec<&AccountInfo<'a>> = vec![&arena_info];
-        for acct in ctx.remaining_accounts.iter() {
-            to_commit.push(acct);
-        }
-        let count = to_commit.len();
-        commit_accounts(
-            &ctx.accounts.payer,
-            to_commit,
-            &ctx.accounts.magic_context,
-            &ctx.accounts.magic_program,
-        )?;
-        msg!("State committed to base layer ({} accounts)", count);
+    pub fn register_player(ctx: Context<RegisterPlayer>, wallet: Pubkey) -> Result<()> {
+        require_allowlisted(&ctx.accounts.arena, ctx.accounts.allowlist.as_deref(), &wallet)?;
+        let player = &mut ctx.accounts.player_state;
+        init_base_player(player, wallet);
+        let arena = &mut ctx.accounts.arena;
+        player.arena = arena.key();
+        // Arena start level/XP, with HP fitted to hp_scale_bps
+        apply_start_profile(player, &arena.config);
+        arena.alive_count = arena.alive_count.saturating_add(1);
+        msg!("Player {} registered", wallet);
         Ok(())
     }
 
-    pub fn commit_player(ctx: Context<CommitPlayer>) -> Result<()> {
-        commit_accounts(
-            &ctx.accounts.payer,
-            vec![&ctx.accounts.player_state.to_account_info()],
-            &ctx.accounts.magic_context,
-            &ctx.accounts.magic_program,
-        )?;
-        msg!("Player committed to base layer");
-        Ok(())
+    /// Register up to MAX_BATCH_REGISTER players in one go. `remaining_accounts`
+    /// holds each wallet's player PDA, in the same order as `wallets`. Wallets
+    /// that already have an account are skipped. Returns how many were created.
+    pub fn register_players_batch<'a>(
+        ctx: Context<'_, '_, 'a, 'a, RegisterPlayersBatch<'a>>,
+        wallets: Vec<Pubkey>,
+    ) -> Result<u32> {
+        require!(
+            !wallets.is_empty() && wallets.len() <= MAX_BATCH_REGISTER,
+            CombatError::InvalidBatchSize
+        );
+        require!(
+            ctx.remaining_accounts.len() == wallets.len(),
+            CombatError::InvalidBatchSize
+        );
+
+        let authority = ctx.accounts.authority.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let lamports = Rent::get()?.minimum_balance(PLAYER_STATE_SPACE);
+        let mut created: u32 = 0;
+
+        for (wallet, acct) in wallets.iter().zip(ctx.remaining_accounts.iter()) {
+            require_allowlisted(&ctx.accounts.arena, ctx.accounts.allowlist.as_deref(), wallet)?;
+            let (pda, bump) = Pubkey::find_program_address(&[PLAYER_SEED, wallet.as_ref()], &crate::ID);
+            require_keys_eq!(acct.key(), pda, CombatError::InvalidPlayerAccount);
+
+            // Delegated players are owned by the delegation program but still
+            // registered; any other account with data is not ours to claim.
+            if acct.owner == &crate::ID || acct.owner == &DELEGATION_PROGRAM_ID || !acct.data_is_empty() {
+                msg!("Player {} already registered, skipping", wallet);
+                continue;
+            }
+
+            let signer_seeds: &[&[u8]] = &[PLAYER_SEED, wallet.as_ref(), &[bump]];
+            let current = acct.lamports();
+            if current == 0 {
+                anchor_lang::system_program::create_account(
+                    CpiContext::new_with_signer(
+                        system_program.clone(),
+                        anchor_lang::system_program::CreateAccount {
+                            from: authority.clone(),
+                            to: acct.clone(),
+                        },
+                        &[signer_seeds],
+                    ),
+                    lamports,
+                    PLAYER_STATE_SPACE as u64,
+                    &crate::ID,
+                )?;
+            } else {
+                // Someone pre-funded the PDA; top it up and claim it instead.
+                if lamports > current {
+                    anchor_lang::system_program::transfer(
+                        CpiContext::new(
+                            system_program.clone(),
+                            anchor_lang::system_program::Transfer {
+                                from: authority.clone(),
+                                to: acct.clone(),
+                            },
+                        ),
+                        lamports - current,
+                    )?;
+                }
+                anchor_lang::system_program::allocate(
+                    CpiContext::new_with_signer(
+                        system_program.clone(),
+                        anchor_lang::system_program::Allocate { account_to_allocate: acct.clone() },
+                        &[signer_seeds],
+                    ),
+                    PLAYER_STATE_SPACE as u64,
+                )?;
+                anchor_lang::system_program::assign(
+                    CpiContext::new_with_signer(
+                        system_program.clone(),
+                        anchor_lang::system_program::Assign { account_to_assign: acct.clone() },
+                        &[signer_seeds],
+                    ),
+                    &crate::ID,
+                )?;
+            }
+
+            let mut player = PlayerState::default();
+            init_base_player(&mut player, *wallet);
+            player.arena = ctx.accounts.arena.key();
+            apply_start_profile(&mut player, &ctx.accounts.arena.config);
+            player.try_serialize(&mut &mut acct.try_borrow_mut_data()?[..])?;
+            created += 1;
+        }
+
+        let arena = &mut ctx.accounts.arena;
+        arena.player_count = arena.player_count.saturating_add(created);
+        arena.alive_count = arena.alive_count.saturating_add(created);
+        msg!(
+            "Batch registered {} of {} players (arena players: {})",
+            created, wallets.len(), arena.player_count
+        );
+        Ok(created)
     }
 
-    pub fn end_session<'a>(ctx: Context<'_, '_, 'a, 'a, EndSession<'a>>) -> Result<()> {
-        let arena_info = ctx.accounts.arena.to_account_info();
-        let mut to_commit: Vec<&AccountInfo<'a>> = vec![&arena_info];
-        for acct in ctx.remaining_accounts.iter() {
-            to_commit.push(acct);
+    /// Create a practice target. Dummies take damage like any player but reset to
+    /// full HP instead of dying, and never feed kills or XP to their attacker.
+    pub fn init_dummy(ctx: Context<InitDummy>, wallet: Pubkey) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        init_base_player(player, wallet);
+        player.arena = ctx.accounts.arena.key();
+        sync_stats_to_levels(player, &ctx.accounts.arena.config);
+        // Full HP on the arena's scale, as a registered player starts
+        player.health = effective_max_health(player, &ctx.accounts.arena.config).min(u16::MAX as u32) as u16;
+        player.is_dummy = true;
+        msg!("Dummy {} created", wallet);
+        Ok(())
+    }
+
+    pub fn delegate_arena(ctx: Context<DelegateArenaCtx>, arena_id: u16) -> Result<()> {
+        if is_delegated(&ctx.accounts.arena, &ctx.accounts.delegation_program) {
+            msg!("Arena {} already delegated", arena_id);
+            return Ok(());
         }
-        let count = to_commit.len();
-        commit_and_undelegate_accounts(
+        let id_seed = arena_id_seed(arena_id);
+        ctx.accounts.delegate_arena(
             &ctx.accounts.payer,
-            to_commit,
-            &ctx.accounts.magic_context,
-            &ctx.accounts.magic_program,
+            &[ARENA_SEED, &id_seed],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|a| a.key()),
+                ..Default::default()
+            },
         )?;
-        msg!("Session ended, {} accounts undelegated", count);
+        emit!(ArenaDelegatedEvent {
+            arena: ctx.accounts.arena.key(),
+            authority: ctx.accounts.payer.key(),
+            timestamp: checked_now()?,
+        });
+        msg!("Arena {} delegated to ER", arena_id);
         Ok(())
     }
-}
 
-// ─── Talent prerequisite chain ───────────────────────────────────────────────
-// Each tree: T1 → T2 → T3 → T4 → T5(capstone)
-// Returns the talent slot that must have ≥1 rank before this talent can be allocated.
-fn talent_prerequisite(talent_id: u8) -> Option<u8> {
-    match talent_id {
-        // Tank: 0 → 1 → 2 → 3 → 4
-        0 => None,
-        1 => Some(0),
-        2 => Some(1),
-        3 => Some(2),
-        4 => Some(3),
-        // Firepower: 5 → 6 → 7 → 8 → 9
-        5 => None,
-        6 => Some(5),
-        7 => Some(6),
-        8 => Some(7),
-        9 => Some(8),
-        // Brawler: 10 → 11 → 12 → 13 → 14
-        10 => None,
-        11 => Some(10),
-        12 => Some(11),
-        13 => Some(12),
-        14 => Some(13),
-        // Mass Damage: 15 → 16 → 19 → 18 → 17
-        // (ricochet → counterAttack → focusFire → nova → chainLightning)
-        15 => None,
-        16 => Some(15),
-        19 => Some(16),
-        18 => Some(19),
-        17 => Some(18),
-        // Blood Thirst: 20 → 21 → 22 → 23 → 24
-        20 => None,
-        21 => Some(20),
-        22 => Some(21),
-        23 => Some(22),
-        24 => Some(23),
-        _ => None,
+    pub fn delegate_player(ctx: Context<DelegatePlayerCtx>, wallet: Pubkey) -> Result<()> {
+        if is_delegated(&ctx.accounts.player_state, &ctx.accounts.delegation_program) {
+            msg!("Player {} already delegated", wallet);
+            return Ok(());
+        }
+        ctx.accounts.delegate_player_state(
+            &ctx.accounts.payer,
+            &[PLAYER_SEED, wallet.as_ref()],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|a| a.key()),
+                ..Default::default()
+            },
+        )?;
+        msg!("Player {} delegated to ER", wallet);
+        Ok(())
+    }
+
+    pub fn delegate_dummy(ctx: Context<DelegateDummyCtx>, wallet: Pubkey) -> Result<()> {
+        if is_delegated(&ctx.accounts.player_state, &ctx.accounts.delegation_program) {
+            msg!("Dummy {} already delegated", wallet);
+            return Ok(());
+        }
+        ctx.accounts.delegate_player_state(
+            &ctx.accounts.payer,
+            &[DUMMY_SEED, wallet.as_ref()],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|a| a.key()),
+                ..Default::default()
+            },
+        )?;
+        msg!("Dummy {} delegated to ER", wallet);
+        Ok(())
+    }
+
+    /// Delegate a Duel to the ER so `process_attack` can decide it there.
+    pub fn delegate_duel(ctx: Context<DelegateDuelCtx>, challenger: Pubkey) -> Result<()> {
+        if is_delegated(&ctx.accounts.duel, &ctx.accounts.delegation_program) {
+            msg!("Duel of {} already delegated", challenger);
+            return Ok(());
+        }
+        ctx.accounts.delegate_duel(
+            &ctx.accounts.payer,
+            &[DUEL_SEED, challenger.as_ref()],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|a| a.key()),
+                ..Default::default()
+            },
+        )?;
+        msg!("Duel of {} delegated to ER", challenger);
+        Ok(())
+    }
+
+    /// Match start in one transaction: delegate the caller's player and make
+    /// sure the arena is delegated too. Either side already being delegated is
+    /// fine, so racing clients can all call this safely.
+    pub fn join_session(ctx: Context<JoinSession>, arena_id: u16) -> Result<()> {
+        let validator = ctx.remaining_accounts.first().map(|a| a.key());
+        let wallet = ctx.accounts.payer.key();
+        let player = PlayerState::try_deserialize(&mut &ctx.accounts.player_state.try_borrow_data()?[..])?;
+        require_keys_eq!(player.arena, ctx.accounts.arena.key(), CombatError::WrongArena);
+
+        let arena_newly_delegated = !is_delegated(&ctx.accounts.arena, &ctx.accounts.delegation_program);
+        if arena_newly_delegated {
+            let id_seed = arena_id_seed(arena_id);
+            ctx.accounts.delegate_arena(
+                &ctx.accounts.payer,
+                &[ARENA_SEED, &id_seed],
+                DelegateConfig { validator, ..Default::default() },
+            )?;
+        }
+        if !is_delegated(&ctx.accounts.player_state, &ctx.accounts.delegation_program) {
+            ctx.accounts.delegate_player_state(
+                &ctx.accounts.payer,
+                &[PLAYER_SEED, wallet.as_ref()],
+                DelegateConfig { validator, ..Default::default() },
+            )?;
+        }
+
+        emit!(PlayerJoinedEvent {
+            wallet,
+            arena: ctx.accounts.arena.key(),
+            arena_newly_delegated,
+            timestamp: checked_now()?,
+        });
+        msg!("Player {} joined arena {} (arena newly delegated: {})", wallet, arena_id, arena_newly_delegated);
+        Ok(())
+    }
+
+    /// Process combat: the ER computes damage from on-chain talent state.
+    /// Server sends hit_count (how many bullets connected), chain resolves damage.
+    /// The server CANNOT dictate damage — the chain is authoritative.
+    /// Returns the per-hit damage breakdown as return data so the server can
+    /// reconcile its own prediction stage by stage. `distance_bucket` is the
+    /// server's range band for the shot (see `falloff_table`).
+    pub fn process_attack(ctx: Context<ProcessAttack>, hit_count: u16, distance_bucket: u8) -> Result<DamageBreakdown> {
+        let tables = load_talent_tables(&ctx.accounts.talent_tables)?;
+        let attacker = &mut ctx.accounts.attacker;
+        let victim = &mut ctx.accounts.victim;
+        let arena = &mut ctx.accounts.arena;
+
+        require!(attacker.initialized, CombatError::NotInitialized);
+        require!(victim.initialized, CombatError::NotInitialized);
+        require!(attacker.is_alive, CombatError::AttackerDead);
+        require!(!attacker.is_dummy, CombatError::DummyCannotAttack);
+        require!(victim.is_alive, CombatError::VictimDead);
+        require!(arena.is_active, CombatError::ArenaInactive);
+        require!(
+            hit_count > 0 && hit_count <= arena.config.max_hit_count,
+            CombatError::InvalidHitCount
+        );
+        // Duelists only fight each other, and their kill has to reach the Duel
+        require!(can_fight(attacker, victim), CombatError::DuelRestricted);
+        let in_duel = attacker.duel != Pubkey::default();
+        if in_duel {
+            require!(
+                ctx.accounts.duel.as_ref().is_some_and(|d| d.key() == attacker.duel && d.arena == arena.key()),
+                CombatError::DuelRestricted
+            );
+        }
+        // Warmup: the attack is valid but lands for nothing
+        if arena.phase == PHASE_WARMUP {
+            return Ok(DamageBreakdown::default());
+        }
+
+        // Effective max HP stays fixed for the whole instruction; computing it
+        // once here saves the repeats in the damage, execute, kill and sustain steps.
+        let attacker_eff_max = effective_max_health(attacker, &arena.config);
+        let victim_eff_max = effective_max_health(victim, &arena.config);
+
+        let now = checked_now()?;
+        require!(can_be_hit(victim, now), CombatError::VictimDead);
+        attacker.last_combat_at = now;
+        victim.last_combat_at = now;
+        let victim_in_execute_range = in_execute_range(victim, victim_eff_max, &arena.config);
+        let slot = Clock::get()?.slot;
+        let seed = crit_seed(attacker, victim, now, slot);
+        let mut breakdown = volley_breakdown(
+            attacker, victim, &arena.config, &tables,
+            (attacker_eff_max, victim_eff_max), now, seed, hit_count,
+        );
+        // Range falloff scales the finished per-hit damage
+        breakdown.final_damage = apply_falloff(breakdown.final_damage, distance_bucket, &arena.config)?;
+        if breakdown.crit_hits > 0 {
+            breakdown.crit_damage = apply_falloff(breakdown.crit_damage, distance_bucket, &arena.config)?;
+        }
+        let damage_per_hit = breakdown.final_damage;
+        let mut total_damage = calc_volley_damage(&breakdown, hit_count, &arena.config);
+        if !victim.is_dummy {
+            total_damage = clamp_life_damage(victim, total_damage, victim_eff_max);
+            if now < victim.invulnerable_until {
+                total_damage = 0;
+            }
+            // Last Stand (slot 4 = talent_armor): the first lethal volley of a
+            // life leaves the victim at 1 HP and briefly invulnerable
+            let last_stand_rank = talent_rank(victim.talent_armor, &arena.config);
+            if victim.health <= total_damage
+                && arena.config.last_stand
+                && last_stand_rank > 0
+                && !victim.last_stand_used
+            {
+                total_damage = victim.health.saturating_sub(1);
+                victim.last_stand_used = true;
+                victim.invulnerable_until = now + LAST_STAND_INVULN_SECS[last_stand_rank.min(3) as usize - 1];
+                emit!(LastStandEvent {
+                    wallet: victim.wallet,
+                    attacker: attacker.wallet,
+                    invulnerable_until: victim.invulnerable_until,
+                });
+                msg!("LAST STAND: {} survives {} at 1 HP", victim.wallet, attacker.wallet);
+            }
+            victim.damage_taken_this_life = victim.damage_taken_this_life.saturating_add(total_damage);
+        }
+
+        victim.total_damage_taken = victim.total_damage_taken.saturating_add(total_damage as u64);
+        arena.dirty_since_commit = arena.dirty_since_commit.saturating_add(1);
+        bump_event_seq(arena);
+
+        if victim.is_dummy {
+            // Dummies soak every hit: a lethal volley refills them instead of killing.
+            if victim.health <= total_damage {
+                victim.health = victim_eff_max as u16;
+            } else {
+                victim.health -= total_damage;
+            }
+            msg!(
+                "DUMMY: {} -> {} | {} hits, {} dmg/hit, {} total (taken: {})",
+                attacker.wallet, victim.wallet,
+                hit_count, damage_per_hit, total_damage,
+                victim.total_damage_taken
+            );
+            return Ok(breakdown);
+        }
+
+        let damage_dealt = total_damage.min(victim.health);
+        arena.total_damage_dealt = arena.total_damage_dealt.saturating_add(damage_dealt as u128);
+
+        // Top damager this life: one slot, so an attacker either extends their own
+        // tally or takes the slot by out-hitting the current holder in one volley.
+        if victim.top_damager == attacker.wallet {
+            victim.top_damage = victim.top_damage.saturating_add(damage_dealt);
+        } else if damage_dealt > victim.top_damage {
+            victim.top_damager = attacker.wallet;
+            victim.top_damage = damage_dealt;
+        }
+
+        if victim.health <= total_damage {
+            // Pre-death level, shared by the ghost timer and the kill XP
+            let victim_level = apply_death(victim, attacker, arena, now);
+
+            // First kill between duelists decides and closes the duel, freeing both
+            if in_duel {
+                if let Some(duel) = ctx.accounts.duel.as_mut() {
+                    duel.winner = Some(attacker.wallet);
+                    duel.accepted = false;
+                    attacker.duel = Pubkey::default();
+                    victim.duel = Pubkey::default();
+                    emit!(DuelEndedEvent {
+                        duel: duel.key(),
+                        winner: attacker.wallet,
+                        loser: victim.wallet,
+                    });
+                    msg!("DUEL: {} beat {}", attacker.wallet, victim.wallet);
+                }
+            }
+
+            let mut kill_xp = calc_kill_xp(attacker, victim_level as u64, &arena.config);
+
+            // Anti-stomp: far lower-level victims are worth little
+            let stomp_bps = anti_stomp_bps(calc_level(attacker.xp), victim_level, &arena.config);
+            let anti_stomp_xp = kill_xp - kill_xp * stomp_bps / 10000;
+            kill_xp -= anti_stomp_xp;
+
+            // Reaper's Arc (slot 23 = talent_deathbomb): overkill → bonus XP
+            let overkill = (total_damage - damage_dealt) as u64;
+            let mut overkill_xp = (overkill
+                * lookup_bps(talent_rank(attacker.talent_deathbomb, &arena.config), &OVERKILL_XP_BPS) as u64
+                / 10000)
+                .min(kill_xp);
+
+            // Execute (slot 21 = talent_homing): finishing an execute-range victim
+            let mut execute_xp = 0;
+            if arena.config.execute_kill_xp && victim_in_execute_range {
+                execute_xp = kill_xp
+                    * lookup_bps(talent_rank(attacker.talent_homing, &arena.config), &EXECUTE_KILL_XP_BPS) as u64
+                    / 10000;
+            }
+
+            // Anti-farm: repeat kills on the same victim in a short span pay less
+            if arena.config.anti_farm {
+                let repeats = record_victim(attacker, victim.wallet, now);
+                let farm_bps = ANTI_FARM_XP_BPS[repeats];
+                kill_xp = kill_xp * farm_bps / 10000;
+                overkill_xp = overkill_xp * farm_bps / 10000;
+                execute_xp = execute_xp * farm_bps / 10000;
+            }
+
+            // Event multiplier last, on top of every talent and bounty bonus
+            let kill_xp = scale_xp(kill_xp, &arena.config);
+            let overkill_xp = scale_xp(overkill_xp, &arena.config);
+            let execute_xp = scale_xp(execute_xp, &arena.config);
+            attacker.xp += kill_xp + overkill_xp + execute_xp;
+
+            // Bounty board: a flat payout, outside the event multiplier
+            let bounty_xp = claim_bounty(arena, victim.wallet);
+            if bounty_xp > 0 {
+                attacker.xp += bounty_xp;
+                emit!(BountyClaimedEvent {
+                    arena: arena.key(),
+                    killer: attacker.wallet,
+                    target: victim.wallet,
+                    bounty_xp,
+                });
+                msg!("BOUNTY: {} claimed {} XP for {}", attacker.wallet, bounty_xp, victim.wallet);
+            }
+
+            // Kill Rush (slot 22 = talent_ricochet): burst heal on the kill
+            let mut kill_heal: u16 = 0;
+            let kill_rush = lookup_bps(talent_rank(attacker.talent_ricochet, &arena.config), &HEAL_ON_KILL_BPS);
+            if kill_rush > 0 {
+                let heal = attacker_eff_max * kill_rush / 10000;
+                let new_health = (attacker.health as u32 + heal).min(attacker_eff_max) as u16;
+                kill_heal = new_health.saturating_sub(attacker.health);
+                attacker.health = new_health;
+            }
+
+            // Per-player win: the attacker is the only one whose kills change in
+            // this instruction, so it is checked first and the first to arrive
+            // keeps the win.
+            if arena.config.player_kill_goal > 0
+                && arena.winner.is_none()
+                && attacker.kills >= arena.config.player_kill_goal
+            {
+                arena.winner = Some(attacker.wallet);
+                arena.is_active = false;
+                emit!(PlayerWonEvent {
+                    arena: arena.key(),
+                    winner: attacker.wallet,
+                    kills: attacker.kills,
+                });
+            }
+
+            // First-to-N arenas close as soon as the goal is reached; later
+            // attacks then fail the is_active check.
+            if arena.is_active && arena.config.kill_goal > 0 && arena.total_kills >= arena.config.kill_goal {
+                arena.is_active = false;
+                emit!(MatchEndedEvent {
+                    arena: arena.key(),
+                    winner: attacker.wallet,
+                    total_kills: arena.total_kills,
+                });
+            }
+
+            // Elimination: nobody respawns, so the attacker landing the kill that
+            // leaves one player standing is the survivor.
+            if arena.is_active && arena.config.elimination_mode && arena.alive_count <= 1 {
+                arena.is_active = false;
+                if arena.winner.is_none() {
+                    arena.winner = Some(attacker.wallet);
+                }
+                emit!(EliminationWinnerEvent {
+                    arena: arena.key(),
+                    winner: attacker.wallet,
+                    kills: attacker.kills,
+                });
+            }
+
+            auto_level(attacker, &arena.config);
+
+            msg!(
+                "KILL: {} -> {} | {} hits, {} dmg/hit, {} total | +{} XP (+{} overkill, +{} execute) +{} HP | arena kills: {}",
+                attacker.wallet, victim.wallet,
+                hit_count, damage_per_hit, total_damage,
+                kill_xp, overkill_xp, execute_xp, kill_heal, arena.total_kills
+            );
+            emit!(KillEvent {
+                attacker: attacker.wallet,
+                victim: victim.wallet,
+                total_damage,
+                kill_xp,
+                overkill_xp,
+                execute_xp,
+                anti_stomp_xp,
+                kill_heal,
+                top_damager: victim.top_damager,
+                top_damage: victim.top_damage,
+            });
+        } else {
+            victim.health -= total_damage;
+            msg!(
+                "HIT: {} -> {} | {} hits, {} dmg/hit, {} total (hp: {}/{})",
+                attacker.wallet, victim.wallet,
+                hit_count, damage_per_hit, total_damage,
+                victim.health, victim.max_health
+            );
+        }
+
+        let (healed, countered) = apply_sustain(
+            attacker, victim, damage_dealt, hit_count, &arena.config, &tables,
+            (attacker_eff_max, victim_eff_max), now, slot,
+        );
+        if healed > 0 || countered > 0 {
+            msg!(
+                "SUSTAIN: {} lifesteal +{} counter -{} (hp: {})",
+                attacker.wallet, healed, countered, attacker.health
+            );
+        }
+
+        Ok(breakdown)
+    }
+
+    /// Dry-run `process_attack` for balance tooling. Runs the same damage and
+    /// kill-XP math and returns the outcome as return data; nothing is written.
+    pub fn simulate_attack(ctx: Context<SimulateAttack>, hit_count: u16, distance_bucket: u8) -> Result<AttackPreview> {
+        let attacker = &ctx.accounts.attacker;
+        let victim = &ctx.accounts.victim;
+        let arena = &ctx.accounts.arena;
+
+        require!(attacker.initialized, CombatError::NotInitialized);
+        require!(victim.initialized, CombatError::NotInitialized);
+        require!(
+            hit_count > 0 && hit_count <= arena.config.max_hit_count,
+            CombatError::InvalidHitCount
+        );
+
+        let now = checked_now()?;
+        let tables = load_talent_tables(&ctx.accounts.talent_tables)?;
+        let eff_max = (effective_max_health(attacker, &arena.config), effective_max_health(victim, &arena.config));
+        let mut breakdown = volley_breakdown(
+            attacker, victim, &arena.config, &tables,
+            eff_max, now, crit_seed(attacker, victim, now, Clock::get()?.slot), hit_count,
+        );
+        breakdown.final_damage = apply_falloff(breakdown.final_damage, distance_bucket, &arena.config)?;
+        if breakdown.crit_hits > 0 {
+            breakdown.crit_damage = apply_falloff(breakdown.crit_damage, distance_bucket, &arena.config)?;
+        }
+        let damage_per_hit = breakdown.final_damage;
+        let total_damage = calc_volley_damage(&breakdown, hit_count, &arena.config);
+        let would_kill = !victim.is_dummy && victim.health <= total_damage;
+        let kill_xp_if_killed = if victim.is_dummy {
+            0
+        } else {
+            calc_kill_xp(attacker, calc_level(victim.xp) as u64, &arena.config)
+        };
+
+        Ok(AttackPreview {
+            damage_per_hit,
+            total_damage,
+            would_kill,
+            kill_xp_if_killed,
+        })
+    }
+
+    /// Seconds until the player may respawn, by the validator's clock rather
+    /// than the client's. 0 for alive players and once the timer has run out.
+    pub fn get_respawn_countdown(ctx: Context<GetRespawnCountdown>) -> Result<RespawnCountdown> {
+        let player = &ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        let seconds = if player.is_alive {
+            0
+        } else {
+            (player.respawn_at - checked_now()?).max(0) as u32
+        };
+        Ok(RespawnCountdown {
+            seconds,
+            is_alive: player.is_alive,
+        })
+    }
+
+    /// Read-only snapshot of the values the client derives from a player's
+    /// on-chain state, computed with the arena's config.
+    pub fn get_player_summary(ctx: Context<GetPlayerSummary>) -> Result<PlayerSummary> {
+        let player = &ctx.accounts.player_state;
+        let config = &ctx.accounts.arena.config;
+        require!(player.initialized, CombatError::NotInitialized);
+        let tables = load_talent_tables(&ctx.accounts.talent_tables)?;
+
+        let level = calc_level(player.xp);
+        Ok(PlayerSummary {
+            level,
+            talent_points: talent_budget(level, config),
+            talent_points_spent: player.total_talent_points_spent(),
+            effective_max_health: effective_max_health(player, config),
+            active_synergies: active_synergies(player, config),
+            crit_chance_bps: lookup_bps(talent_rank(player.talent_evasion, config), &tables.crit_chance_bps),
+            crit_mult_bps: lookup_bps(talent_rank(player.talent_evasion, config), &tables.crit_mult_bps),
+        })
+    }
+
+    /// Arena-wide balance telemetry as return data.
+    pub fn get_arena_stats(ctx: Context<GetArenaStats>) -> Result<ArenaStats> {
+        let arena = &ctx.accounts.arena;
+        Ok(ArenaStats {
+            player_count: arena.player_count,
+            total_kills: arena.total_kills,
+            total_damage_dealt: arena.total_damage_dealt,
+            is_active: arena.is_active,
+            unique_killers: arena.unique_killers,
+        })
+    }
+
+    /// When the server should next commit, and how much has changed since the
+    /// last one. The server can commit early when activity is high, but never
+    /// sooner than MIN_COMMIT_INTERVAL_SECS after the previous commit.
+    pub fn next_commit_recommended_at(ctx: Context<GetArenaStats>) -> Result<CommitSchedule> {
+        let arena = &ctx.accounts.arena;
+        Ok(CommitSchedule {
+            recommended_at: arena.last_commit_at + MIN_COMMIT_INTERVAL_SECS,
+            dirty_since_commit: arena.dirty_since_commit,
+        })
+    }
+
+    /// Authoritative leaderboard key for a player; see `rank_key` for the
+    /// ordering. Off-chain rankers sort by this, descending.
+    pub fn player_rank_key(ctx: Context<PlayerRankKey>) -> Result<[u8; 56]> {
+        let player = &ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        Ok(rank_key(player))
+    }
+
+    pub fn respawn_player(ctx: Context<RespawnPlayer>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(!player.is_alive, CombatError::AlreadyAlive);
+
+        let now = checked_now()?;
+        // respawn_at is set to at most MAX_GHOST_SECS ahead of the clock at death
+        // (FORFEIT_RESPAWN_SECS after a forfeit). Anything further out means the
+        // clock has run backwards since then, so pull it back to the longest
+        // legitimate wait from now. Returning an error would roll the clamp back
+        // and leave the player stranded.
+        let max_wait = if player.out_of_match { FORFEIT_RESPAWN_SECS } else { MAX_GHOST_SECS };
+        let remaining = player.respawn_at - now;
+        if remaining > max_wait + CLOCK_SKEW_TOLERANCE_SECS {
+            msg!(
+                "CLOCK: {} respawn_at {} is {}s ahead of now {}, clamping to {}s",
+                player.wallet, player.respawn_at, remaining, now, max_wait
+            );
+            player.respawn_at = now + max_wait;
+            return Ok(());
+        }
+        require!(remaining <= 0, CombatError::RespawnCooldown);
+        require!(now >= player.death_grace_until, CombatError::RespawnCooldown);
+        let arena = &mut ctx.accounts.arena;
+        require!(!arena.config.elimination_mode, CombatError::EliminationMode);
+        arena.alive_count = arena.alive_count.saturating_add(1);
+        bump_event_seq(arena);
+        player.out_of_match = false;
+        player.top_damager = Pubkey::default();
+        player.top_damage = 0;
+        player.damage_taken_this_life = 0;
+        player.in_zone = true;
+        player.last_stand_used = false;
+        player.invulnerable_until = 0;
+        player.respawn_pending = false;
+
+        player.health = respawn_health(player, &arena.config);
+        player.is_alive = true;
+        player.respawn_at = 0;
+
+        msg!("Player {} respawned (hp: {})", player.wallet, player.health);
+        Ok(())
+    }
+
+    pub fn upgrade_stat(ctx: Context<UpgradeStat>, stat_type: u8) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+
+        let (current_level, label) = match stat_type {
+            0 => (player.health_level, "health"),
+            1 => (player.attack_level, "attack"),
+            _ => return Err(CombatError::InvalidStatType.into()),
+        };
+
+        require!(current_level < MAX_LEVEL, CombatError::MaxLevel);
+
+        let cost = 100u64 + (current_level as u64) * 50;
+        require!(player.xp >= cost, CombatError::InsufficientXP);
+
+        // Spending XP lowers the derived level, and with it the talent budget.
+        // Refuse the upgrade rather than leave the build over-allocated, unless
+        // the arena refunds the overflow instead.
+        let config = &ctx.accounts.arena.config;
+        if !config.refund_on_overflow {
+            require_build_affordable(player, player.xp - cost)?;
+        }
+
+        player.xp -= cost;
+
+        match stat_type {
+            0 => player.health_level += 1,
+            1 => player.attack_level += 1,
+            _ => unreachable!(),
+        }
+        refund_and_emit(player);
+        sync_stats_to_levels(player, config);
+
+        msg!("Player {} upgraded {} to level {}", player.wallet, label,
+            match stat_type { 0 => player.health_level, _ => player.attack_level });
+        Ok(())
     }
+
+    pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        let arena = &mut ctx.accounts.arena;
+        check_reset(player, arena)?;
+        // reset_to_base revives a dead player
+        if !player.is_alive {
+            arena.alive_count = arena.alive_count.saturating_add(1);
+        }
+        bump_event_seq(arena);
+        // A reset player starts over, so any bounty on them goes unpaid
+        if arena.bounty_target == player.wallet {
+            msg!("Bounty on {} withdrawn ({} XP unpaid)", player.wallet, arena.bounty_xp);
+            clear_bounty(arena);
+        }
+        reset_to_base(player);
+        apply_start_profile(player, &arena.config);
+        msg!("Player {} reset to base stats", player.wallet);
+        Ok(())
+    }
+
+    /// Allocate a talent point with full on-chain validation (see
+    /// `check_talent_allocation`).
+    pub fn allocate_talent(ctx: Context<AllocateTalent>, talent_id: u8) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        check_talent_allocation(player, talent_id, &ctx.accounts.arena.config)?;
+        let current = player.get_talent(talent_id);
+
+        player.set_talent(talent_id, current + 1);
+        player.manual_build = true;
+        debug_assert!((0..25u8).all(|slot| player.get_talent(slot) <= max_rank_for_talent(slot)));
+
+        msg!("Player {} allocated talent {} to rank {}", player.wallet, talent_id, current + 1);
+        Ok(())
+    }
+
+    /// Dry run of `allocate_talent` for UI enablement. `reason` is the
+    /// CombatError variant index `allocate_talent` would fail with (the error
+    /// code minus 6000), and 0 when `allowed`.
+    pub fn can_allocate_talent(ctx: Context<CanAllocateTalent>, talent_id: u8) -> Result<TalentAllocationCheck> {
+        let check = check_talent_allocation(&ctx.accounts.player_state, talent_id, &ctx.accounts.arena.config);
+        Ok(TalentAllocationCheck {
+            allowed: check.is_ok(),
+            reason: check.err().map_or(0, |e| e as u8),
+        })
+    }
+
+    /// Remove a single rank from a talent. Dropping the last rank is refused while
+    /// a talent that depends on it still has ranks. Points are derived from level,
+    /// so nothing is refunded explicitly.
+    pub fn deallocate_talent(ctx: Context<DeallocateTalent>, talent_id: u8) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(talent_id <= 24, CombatError::InvalidTalentId);
+
+        let current = player.get_talent(talent_id);
+        require!(current > 0, CombatError::TalentNotAllocated);
+
+        if current == 1 {
+            let has_dependent = (0..=24u8).any(|id| {
+                talent_prerequisite(id) == Some(talent_id) && player.get_talent(id) > 0
+            });
+            require!(!has_dependent, CombatError::PrerequisiteNotMet);
+        }
+
+        player.set_talent(talent_id, current - 1);
+        player.manual_build = true;
+
+        msg!("Player {} deallocated talent {} to rank {}", player.wallet, talent_id, current - 1);
+        Ok(())
+    }
+
+    /// Replace the player's whole build in one instruction. The loadout must pass
+    /// the same rules as repeated `allocate_talent` calls (see validate_loadout).
+    pub fn apply_talent_loadout(ctx: Context<ApplyTalentLoadout>, ranks: [u8; 25]) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+
+        validate_loadout(&ranks, calc_level(player.xp), &ctx.accounts.arena.config)?;
+        for (slot, &rank) in ranks.iter().enumerate() {
+            player.set_talent(slot as u8, rank);
+        }
+        player.manual_build = true;
+
+        msg!("Player {} applied a loadout ({} points)", player.wallet, player.total_talent_points_spent());
+        Ok(())
+    }
+
+    pub fn reset_talents(ctx: Context<ResetTalents>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+
+        player.talent_iron_skin = 0;
+        player.talent_heavy_hitter = 0;
+        player.talent_regeneration = 0;
+        player.talent_lifesteal = 0;
+        player.talent_armor = 0;
+        player.talent_swift = 0;
+        player.talent_rapid_fire = 0;
+        player.talent_evasion = 0;
+        player.talent_quick_respawn = 0;
+        player.talent_momentum = 0;
+        player.talent_weakspot = 0;
+        player.talent_critical_strike = 0;
+        player.talent_focus_fire = 0;
+        player.talent_multi_shot = 0;
+        player.talent_dual_cannon = 0;
+        player.talent_deflect = 0;
+        player.talent_absorb = 0;
+        player.talent_last_stand = 0;
+        player.talent_cloak = 0;
+        player.talent_dash = 0;
+        player.talent_rampage = 0;
+        player.talent_homing = 0;
+        player.talent_ricochet = 0;
+        player.talent_deathbomb = 0;
+        player.talent_frenzy = 0;
+        player.manual_build = true;
+        msg!("Player {} talents reset", player.wallet);
+        Ok(())
+    }
+
+    /// Grow a player account to the current layout. Returns whether anything
+    /// changed: `false` when it is already at PLAYER_SCHEMA_VERSION. Accounts that
+    /// are not PlayerStates (MigrationBadDiscriminator), larger than the current
+    /// layout (MigrationAccountTooLarge) or carry an unknown version
+    /// (InvalidMigration) are rejected. Cooldowns left in the superseded
+    /// per-ability fields move into `ability_cooldowns`.
+    pub fn migrate_player(ctx: Context<MigratePlayer>) -> Result<bool> {
+        let player_info = &ctx.accounts.player_state;
+        let current_len = player_info.data_len();
+        let target_len: usize = PLAYER_STATE_SPACE;
+        let version = player_schema_version(&player_info.try_borrow_data()?)?;
+
+        if version == Some(PLAYER_SCHEMA_VERSION) {
+            msg!("Account already at schema version {}, no migration needed", PLAYER_SCHEMA_VERSION);
+            return Ok(false);
+        }
+
+        let rent = Rent::get()?;
+        let new_min = rent.minimum_balance(target_len);
+        let old_balance = player_info.lamports();
+        if new_min > old_balance {
+            let diff = new_min - old_balance;
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.authority.to_account_info(),
+                        to: player_info.to_account_info(),
+                    },
+                ),
+                diff,
+            )?;
+        }
+
+        #[allow(deprecated)]
+        player_info.realloc(target_len, false)?;
+
+        let mut data = player_info.try_borrow_mut_data()?;
+        if version.is_some() {
+            // The old version byte sits where the fields added since begin
+            data[current_len - 1] = 0;
+        }
+        data[target_len - 1] = PLAYER_SCHEMA_VERSION;
+        // Players from before the arena binding belong to the legacy arena 0
+        if !matches!(version, Some(v) if v >= ARENA_BINDING_SCHEMA) {
+            let (legacy_arena, _) = Pubkey::find_program_address(&[ARENA_SEED], &crate::ID);
+            data[PLAYER_ARENA_OFFSET..PLAYER_ARENA_OFFSET + 32].copy_from_slice(legacy_arena.as_ref());
+        }
+        let mut player = PlayerState::try_deserialize(&mut &data[..])?;
+        carry_legacy_cooldowns(&mut player);
+        player.try_serialize(&mut &mut data[..])?;
+        drop(data);
+
+        msg!(
+            "Player account migrated from {} to {} bytes (schema {:?} -> {})",
+            current_len, target_len, version, PLAYER_SCHEMA_VERSION
+        );
+        Ok(true)
+    }
+
+    pub fn commit_state<'a>(ctx: Context<'_, '_, 'a, 'a, CommitState<'a>>) -> Result<CommitReceipt> {
+        require_committable(ctx.remaining_accounts)?;
+        mark_committed(&mut ctx.accounts.arena)?;
+        ctx.accounts.arena.exit(&crate::ID)?;
+        let arena_info = ctx.accounts.arena.to_account_info();
+        let mut to_commit: Vec<&AccountInfo<'a>> = vec![&arena_info];
+        for acct in ctx.remaining_accounts.iter() {
+            to_commit.push(acct);
+        }
+        let count = to_commit.len();
+        commit_accounts(
+            &ctx.accounts.payer,
+            to_commit,
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+        msg!("State committed to base layer ({} accounts)", count);
+        commit_receipt(&ctx.accounts.arena, count)
+    }
+
+    /// Settle a large session in bounded groups. Each call commits the players
+    /// in `remaining_accounts` (undelegating them too when `undelegate` is set);
+    /// the arena itself is only committed, or undelegated, with the last chunk.
+    /// Chunks must arrive in order starting at 0; after a crash the server reads
+    /// `arena.commit_chunk_next` and continues from there.
+    pub fn commit_state_chunked<'a>(
+        ctx: Context<'_, '_, 'a, 'a, CommitState<'a>>,
+        chunk_index: u16,
+        chunk_total: u16,
+        undelegate: bool,
+    ) -> Result<CommitReceipt> {
+        require_committable(ctx.remaining_accounts)?;
+        require!(chunk_index < chunk_total, CombatError::InvalidCommitChunk);
+        let arena = &mut ctx.accounts.arena;
+        if chunk_index == 0 {
+            arena.commit_chunk_total = chunk_total;
+        } else {
+            require!(
+                chunk_total == arena.commit_chunk_total && chunk_index == arena.commit_chunk_next,
+                CombatError::InvalidCommitChunk
+            );
+        }
+
+        let is_last = chunk_index + 1 == chunk_total;
+        if is_last {
+            arena.commit_chunk_next = 0;
+            arena.commit_chunk_total = 0;
+            mark_committed(arena)?;
+        } else {
+            arena.commit_chunk_next = chunk_index + 1;
+        }
+        if is_last && undelegate {
+            emit!(SessionEndedEvent {
+                arena: arena.key(),
+                authority: arena.authority,
+                unique_killers: arena.unique_killers,
+                timestamp: checked_now()?,
+            });
+            next_session(arena);
+        }
+        arena.exit(&crate::ID)?;
+
+        let arena_info = ctx.accounts.arena.to_account_info();
+        let mut to_commit: Vec<&AccountInfo<'a>> = Vec::new();
+        if is_last {
+            to_commit.push(&arena_info);
+        }
+        for acct in ctx.remaining_accounts.iter() {
+            to_commit.push(acct);
+        }
+        let count = to_commit.len();
+        if undelegate {
+            commit_and_undelegate_accounts(
+                &ctx.accounts.payer,
+                to_commit,
+                &ctx.accounts.magic_context,
+                &ctx.accounts.magic_program,
+            )?;
+        } else {
+            commit_accounts(
+                &ctx.accounts.payer,
+                to_commit,
+                &ctx.accounts.magic_context,
+                &ctx.accounts.magic_program,
+            )?;
+        }
+        msg!(
+            "Commit chunk {}/{} ({} accounts, undelegate: {})",
+            chunk_index + 1, chunk_total, count, undelegate
+        );
+        commit_receipt(&ctx.accounts.arena, count)
+    }
+
+    pub fn commit_player(ctx: Context<CommitPlayer>) -> Result<CommitReceipt> {
+        commit_accounts(
+            &ctx.accounts.payer,
+            vec![&ctx.accounts.player_state.to_account_info()],
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+        msg!("Player committed to base layer");
+        commit_receipt(&ctx.accounts.arena, 1)
+    }
+
+    pub fn end_session<'a>(ctx: Context<'_, '_, 'a, 'a, EndSession<'a>>) -> Result<CommitReceipt> {
+        require_committable(ctx.remaining_accounts)?;
+        // Emitted before the undelegate CPI so it lands in the ER log
+        emit!(SessionEndedEvent {
+            arena: ctx.accounts.arena.key(),
+            authority: ctx.accounts.arena.authority,
+            unique_killers: ctx.accounts.arena.unique_killers,
+            timestamp: checked_now()?,
+        });
+        set_phase(&mut ctx.accounts.arena, PHASE_ENDED)?;
+        next_session(&mut ctx.accounts.arena);
+        mark_committed(&mut ctx.accounts.arena)?;
+        ctx.accounts.arena.exit(&crate::ID)?;
+        let arena_info = ctx.accounts.arena.to_account_info();
+        let mut to_commit: Vec<&AccountInfo<'a>> = vec![&arena_info];
+        for acct in ctx.remaining_accounts.iter() {
+            to_commit.push(acct);
+        }
+        let count = to_commit.len();
+        commit_and_undelegate_accounts(
+            &ctx.accounts.payer,
+            to_commit,
+            &ctx.accounts.magic_context,
+            &ctx.accounts.magic_program,
+        )?;
+        msg!("Session ended, {} accounts undelegated", count);
+        commit_receipt(&ctx.accounts.arena, count)
+    }
+
+    pub fn init_control_point(ctx: Context<InitControlPoint>) -> Result<()> {
+        let point = &mut ctx.accounts.control_point;
+        point.arena = ctx.accounts.arena.key();
+        point.owner = Pubkey::default();
+        point.held_since = 0;
+        point.last_claim_at = 0;
+        msg!("Control point initialized for arena {}", point.arena);
+        Ok(())
+    }
+
+    pub fn delegate_control_point(ctx: Context<DelegateControlPointCtx>, arena_id: u16) -> Result<()> {
+        if is_delegated(&ctx.accounts.control_point, &ctx.accounts.delegation_program) {
+            msg!("Control point of arena {} already delegated", arena_id);
+            return Ok(());
+        }
+        let arena_key = ctx.accounts.arena.key();
+        ctx.accounts.delegate_control_point(
+            &ctx.accounts.payer,
+            &[CONTROL_POINT_SEED, arena_key.as_ref()],
+            DelegateConfig {
+                validator: ctx.remaining_accounts.first().map(|a| a.key()),
+                ..Default::default()
+            },
+        )?;
+        msg!("Control point of arena {} delegated to ER", arena_id);
+        Ok(())
+    }
+
+    /// Take the control point from whoever holds it. The previous holder forfeits
+    /// any XP they had not yet claimed.
+    pub fn capture_point(ctx: Context<CapturePoint>) -> Result<()> {
+        let player = &ctx.accounts.player_state;
+        let point = &mut ctx.accounts.control_point;
+
+        require!(ctx.accounts.arena.is_active, CombatError::ArenaInactive);
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(player.is_alive, CombatError::AttackerDead);
+        require!(!player.is_dummy, CombatError::DummyCannotAttack);
+        require!(point.owner != player.wallet, CombatError::AlreadyHoldingPoint);
+
+        let now = checked_now()?;
+        let previous = point.owner;
+        point.owner = player.wallet;
+        point.held_since = now;
+        point.last_claim_at = now;
+
+        msg!("POINT: {} captured from {}", player.wallet, previous);
+        Ok(())
+    }
+
+    /// Pay the holder XP for the time held since their last claim, bounded by
+    /// POINT_MAX_ACCRUAL_SECS. A holder found dead drops the point instead.
+    pub fn claim_point_xp(ctx: Context<ClaimPointXp>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        let point = &mut ctx.accounts.control_point;
+
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(point.owner == player.wallet, CombatError::NotPointHolder);
+
+        if !player.is_alive {
+            point.owner = Pubkey::default();
+            point.held_since = 0;
+            point.last_claim_at = 0;
+            msg!("POINT: {} lost the point (dead)", player.wallet);
+            return Ok(());
+        }
+
+        let now = checked_now()?;
+        let (xp, elapsed) = claim_point(player, point, now, &ctx.accounts.arena.config);
+
+        msg!(
+            "POINT: {} claimed +{} XP ({}s held, since {})",
+            player.wallet, xp, elapsed, point.held_since
+        );
+        Ok(())
+    }
+
+    /// Shockwave ult: an active burst for Shockwave (slot 14) capstone holders.
+    /// Hits every live player in `remaining_accounts` (up to ULT_MAX_TARGETS) for
+    /// a share of the caster's effective max HP, then goes on cooldown. Ult damage
+    /// leaves targets at 1 HP at worst; kills still come from `process_attack`.
+    pub fn cast_ult<'a>(ctx: Context<'_, '_, 'a, 'a, CastUlt<'a>>) -> Result<()> {
+        let caster = &mut ctx.accounts.caster;
+        let config = &ctx.accounts.arena.config;
+
+        require!(ctx.accounts.arena.is_active, CombatError::ArenaInactive);
+        require!(caster.initialized, CombatError::NotInitialized);
+        require!(caster.is_alive, CombatError::AttackerDead);
+        let rank = talent_rank(caster.talent_dual_cannon, config);
+        require!(rank >= 1, CombatError::UltUnavailable);
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len() <= ULT_MAX_TARGETS,
+            CombatError::InvalidBatchSize
+        );
+
+        let now = checked_now()?;
+        require!(caster.is_ability_ready(ABILITY_ULT, now), CombatError::AbilityCooldown);
+
+        let damage = (effective_max_health(caster, config)
+            * lookup_bps(rank, &ULT_SHOCKWAVE_BPS) / 10000)
+            .min(u16::MAX as u32) as u16;
+
+        let arena_key = ctx.accounts.arena.key();
+        let mut targets: u8 = 0;
+        let mut total_damage: u32 = 0;
+        // Each player takes the shockwave once, however often it is listed
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        for acct in ctx.remaining_accounts.iter() {
+            if seen.contains(acct.key) {
+                continue;
+            }
+            seen.push(*acct.key);
+            let mut target: Account<'a, PlayerState> = Account::try_from(acct)?;
+            if target.wallet == caster.wallet || !target.is_alive || !target.initialized {
+                continue;
+            }
+            require_keys_eq!(target.arena, arena_key, CombatError::WrongArena);
+            // Duelists' shockwaves only reach their opponent, and never a duel from outside
+            if !can_fight(caster, &target) {
+                continue;
+            }
+            let before = target.health;
+            target.health = before.saturating_sub(damage).max(1);
+            let dealt = before - target.health;
+            target.total_damage_taken = target.total_damage_taken.saturating_add(dealt as u64);
+            target.exit(&crate::ID)?;
+            targets += 1;
+            total_damage += dealt as u32;
+        }
+
+        let ready_at = now + ULT_COOLDOWN_SECS[rank.min(3) as usize - 1];
+        caster.set_ability_cooldown(ABILITY_ULT, ready_at);
+
+        emit!(UltCastEvent {
+            caster: caster.wallet,
+            targets,
+            total_damage,
+            ready_at,
+        });
+        msg!(
+            "ULT: {} shockwave hit {} targets for {} total (ready at {})",
+            caster.wallet, targets, total_damage, ready_at
+        );
+        Ok(())
+    }
+
+    /// Leave the match. Signed by the player's own wallet: takes them out of play
+    /// for FORFEIT_RESPAWN_SECS and, if `undelegate` is set, commits and
+    /// undelegates just their account. They re-enter through `respawn_player`.
+    pub fn forfeit(ctx: Context<Forfeit>, undelegate: bool) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(!player.out_of_match, CombatError::AlreadyForfeited);
+
+        let now = checked_now()?;
+        let config = &ctx.accounts.arena.config;
+        let mut xp_penalty = 0;
+        if config.rage_quit_xp_per_streak > 0 && player.current_streak >= config.rage_quit_min_streak {
+            // Never drops the player below their current level, so a spent build
+            // stays valid, unless the arena refunds the overflow instead
+            let floor = if config.refund_on_overflow { 0 } else { level_floor_xp(calc_level(player.xp)) };
+            xp_penalty = (player.current_streak as u64 * config.rage_quit_xp_per_streak)
+                .min(player.xp - floor);
+            player.xp -= xp_penalty;
+            msg!(
+                "RAGE QUIT: {} forfeited on a {}-kill streak, -{} XP",
+                player.wallet, player.current_streak, xp_penalty
+            );
+            refund_and_emit(player);
+        }
+        player.current_streak = 0;
+        let arena = &mut ctx.accounts.arena;
+        if player.is_alive {
+            arena.alive_count = arena.alive_count.saturating_sub(1);
+        }
+        bump_event_seq(arena);
+        // Leaving the match takes the bounty off the board unpaid
+        if arena.bounty_target == player.wallet {
+            msg!("Bounty on {} withdrawn ({} XP unpaid)", player.wallet, arena.bounty_xp);
+            clear_bounty(arena);
+        }
+        player.health = 0;
+        player.is_alive = false;
+        player.out_of_match = true;
+        player.respawn_at = now + FORFEIT_RESPAWN_SECS;
+        player.respawn_pending = true;
+        emit!(RespawnReadyEvent {
+            wallet: player.wallet,
+            respawn_at: player.respawn_at,
+        });
+
+        emit!(ForfeitEvent {
+            wallet: player.wallet,
+            respawn_at: player.respawn_at,
+            undelegated: undelegate,
+            xp_penalty,
+        });
+        msg!("Player {} forfeited (respawn at {})", player.wallet, player.respawn_at);
+
+        if undelegate {
+            player.exit(&crate::ID)?;
+            commit_and_undelegate_accounts(
+                &ctx.accounts.payer,
+                vec![&ctx.accounts.player_state.to_account_info()],
+                &ctx.accounts.magic_context,
+                &ctx.accounts.magic_program,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn init_match_queue(ctx: Context<InitMatchQueue>) -> Result<()> {
+        let queue = &mut ctx.accounts.match_queue;
+        queue.arena = ctx.accounts.arena.key();
+        queue.entries = Vec::new();
+        msg!("Match queue initialized for arena {}", queue.arena);
+        Ok(())
+    }
+
+    /// Put the signer's player in the matchmaking queue at their current level.
+    pub fn join_queue(ctx: Context<JoinQueue>) -> Result<()> {
+        let player = &ctx.accounts.player_state;
+        let queue = &mut ctx.accounts.match_queue;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(!player.is_dummy, CombatError::DummyCannotAttack);
+
+        let now = checked_now()?;
+        queue.evict_stale(now);
+        require!(
+            !queue.entries.iter().any(|e| e.wallet == player.wallet),
+            CombatError::AlreadyQueued
+        );
+        require!(queue.entries.len() < MATCH_QUEUE_MAX, CombatError::QueueFull);
+
+        let level = calc_level(player.xp);
+        queue.entries.push(QueueEntry {
+            wallet: player.wallet,
+            level,
+            enqueued_at: now,
+        });
+        msg!("QUEUE: {} joined at level {} ({} waiting)", player.wallet, level, queue.entries.len());
+        Ok(())
+    }
+
+    /// Crank: pair the two waiting players closest in level (earliest enqueued
+    /// wins ties), remove them from the queue and return their wallets.
+    pub fn pop_match(ctx: Context<PopMatch>) -> Result<MatchPair> {
+        let queue = &mut ctx.accounts.match_queue;
+        let now = checked_now()?;
+        queue.evict_stale(now);
+        require!(queue.entries.len() >= 2, CombatError::NoMatchAvailable);
+
+        let mut best = (0, 1);
+        let mut best_gap = u8::MAX;
+        for i in 0..queue.entries.len() {
+            for j in (i + 1)..queue.entries.len() {
+                let gap = queue.entries[i].level.abs_diff(queue.entries[j].level);
+                if gap < best_gap {
+                    best_gap = gap;
+                    best = (i, j);
+                }
+            }
+        }
+
+        // Remove the later index first so the earlier one stays valid
+        let b = queue.entries.remove(best.1);
+        let a = queue.entries.remove(best.0);
+
+        emit!(MatchFoundEvent {
+            player_a: a.wallet,
+            player_b: b.wallet,
+            level_gap: best_gap,
+        });
+        msg!("MATCH: {} (lvl {}) vs {} (lvl {})", a.wallet, a.level, b.wallet, b.level);
+        Ok(MatchPair {
+            player_a: a.wallet,
+            player_b: b.wallet,
+        })
+    }
+
+    /// Award XP outside combat (quests, reward redemption). Authority only.
+    pub fn grant_xp(ctx: Context<GrantXp>, amount: u64) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(amount > 0 && amount <= MAX_GRANT_XP, CombatError::InvalidGrantAmount);
+
+        let amount = scale_xp(amount, &ctx.accounts.arena.config);
+        player.xp = player.xp.saturating_add(amount);
+        let new_level = calc_level(player.xp);
+        auto_level(player, &ctx.accounts.arena.config);
+
+        emit!(GrantXpEvent {
+            wallet: player.wallet,
+            amount,
+            new_xp: player.xp,
+            new_level,
+        });
+        msg!("Granted {} XP to {} (xp: {}, level: {})", amount, player.wallet, player.xp, new_level);
+        Ok(())
+    }
+
+    /// Taunt another player for up to MAX_TAUNT_SECS: until it expires their
+    /// damage against anyone but the taunter is cut by `taunt_reduction_bps`.
+    pub fn taunt(ctx: Context<Taunt>, duration_secs: i64) -> Result<()> {
+        let taunter = &ctx.accounts.taunter_state;
+        let target = &mut ctx.accounts.target;
+        let config = &ctx.accounts.arena.config;
+
+        require!(ctx.accounts.arena.is_active, CombatError::ArenaInactive);
+        require!(config.taunt_reduction_bps > 0, CombatError::TauntDisabled);
+        require!(taunter.initialized && target.initialized, CombatError::NotInitialized);
+        require!(taunter.is_alive, CombatError::AttackerDead);
+        require!(target.is_alive, CombatError::VictimDead);
+        require!(taunter.wallet != target.wallet, CombatError::InvalidTaunt);
+        require!(duration_secs > 0 && duration_secs <= MAX_TAUNT_SECS, CombatError::InvalidTaunt);
+
+        let now = checked_now()?;
+        require!(now >= target.taunt_until, CombatError::AbilityCooldown);
+        target.taunted_by = taunter.wallet;
+        target.taunt_until = now + duration_secs;
+
+        emit!(TauntEvent {
+            taunter: taunter.wallet,
+            target: target.wallet,
+            until: target.taunt_until,
+        });
+        msg!("TAUNT: {} -> {} until {}", taunter.wallet, target.wallet, target.taunt_until);
+        Ok(())
+    }
+
+    /// Rebuild `max_health`/`attack_power` from `health_level`/`attack_level`,
+    /// repairing players whose stats drifted from their levels (kills used to
+    /// raise levels without stats, and respawns folded Iron Skin into max_health).
+    pub fn reconcile_stats(ctx: Context<ReconcileStats>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        let (old_max, old_attack) = (player.max_health, player.attack_power);
+        sync_stats_to_levels(player, &ctx.accounts.arena.config);
+        msg!(
+            "Player {} reconciled: max_health {} -> {}, attack_power {} -> {}",
+            player.wallet, old_max, player.max_health, old_attack, player.attack_power
+        );
+        Ok(())
+    }
+
+    /// Spend the Dash ability. The server only lets the client's dash through
+    /// when this succeeds.
+    pub fn use_dash(ctx: Context<UseDash>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(player.is_alive, CombatError::AttackerDead);
+
+        // Dash (slot 10 = talent_weakspot)
+        let rank = talent_rank(player.talent_weakspot, &ctx.accounts.arena.config);
+        require!(rank >= 1, CombatError::DashUnavailable);
+
+        let now = checked_now()?;
+        require!(player.is_ability_ready(ABILITY_DASH, now), CombatError::AbilityCooldown);
+        let ready_at = now + DASH_COOLDOWN_SECS[rank.min(5) as usize - 1];
+        player.set_ability_cooldown(ABILITY_DASH, ready_at);
+
+        emit!(DashUsedEvent {
+            wallet: player.wallet,
+            ready_at,
+        });
+        msg!("DASH: {} (ready at {})", player.wallet, ready_at);
+        Ok(())
+    }
+
+    /// Apply base out-of-combat regen to a player. Permissionless: the heal only
+    /// covers time since the player's last tick, so extra calls gain nothing.
+    pub fn tick_regen(ctx: Context<TickRegen>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        let config = &ctx.accounts.arena.config;
+        let now = checked_now()?;
+        if player.is_alive {
+            let eff_max = effective_max_health(player, config);
+            let heal = out_of_combat_regen(player, config, eff_max, now);
+            if heal > 0 {
+                player.health = (player.health as u32 + heal) as u16;
+                msg!("REGEN: {} +{} HP ({}/{})", player.wallet, heal, player.health, eff_max);
+            }
+        }
+        player.last_regen_at = now;
+        Ok(())
+    }
+
+    /// Active burst heal: restores `heal_bps` of effective max HP, then goes on
+    /// cooldown. Only usable mid-fight, i.e. within `out_of_combat_secs` of the
+    /// player's last hit dealt or taken.
+    pub fn use_heal(ctx: Context<UseHeal>) -> Result<()> {
+        let config = &ctx.accounts.arena.config;
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(player.is_alive, CombatError::AttackerDead);
+        require!(config.heal_bps > 0, CombatError::HealUnavailable);
+
+        let now = checked_now()?;
+        require!(player.is_ability_ready(ABILITY_HEAL, now), CombatError::AbilityCooldown);
+        require!(
+            player.last_combat_at > 0 && now - player.last_combat_at <= config.out_of_combat_secs as i64,
+            CombatError::NotInCombat
+        );
+
+        let eff_max = effective_max_health(player, config);
+        let current = player.health as u32;
+        let healed = (eff_max * config.heal_bps as u32 / 10000).min(eff_max.saturating_sub(current));
+        player.health = (current + healed) as u16;
+        let ready_at = now + config.heal_cooldown_secs as i64;
+        player.set_ability_cooldown(ABILITY_HEAL, ready_at);
+
+        emit!(HealUsedEvent {
+            wallet: player.wallet,
+            healed: healed as u16,
+            ready_at,
+        });
+        msg!("HEAL: {} +{} HP (ready at {})", player.wallet, healed, ready_at);
+        Ok(())
+    }
+
+    pub fn init_allowlist(ctx: Context<InitAllowlist>) -> Result<()> {
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.arena = ctx.accounts.arena.key();
+        allowlist.wallets = Vec::new();
+        msg!("Allowlist initialized for arena {}", allowlist.arena);
+        Ok(())
+    }
+
+    /// Admit `wallet` to a private arena. Adding a listed wallet is a no-op.
+    pub fn add_to_allowlist(ctx: Context<UpdateAllowlist>, wallet: Pubkey) -> Result<()> {
+        let allowlist = &mut ctx.accounts.allowlist;
+        if allowlist.wallets.contains(&wallet) {
+            return Ok(());
+        }
+        require!(allowlist.wallets.len() < ALLOWLIST_MAX, CombatError::AllowlistFull);
+        allowlist.wallets.push(wallet);
+        msg!("Allowlisted {} ({} of {})", wallet, allowlist.wallets.len(), ALLOWLIST_MAX);
+        Ok(())
+    }
+
+    /// Drop `wallet` from the allowlist. Already-registered players keep their
+    /// account; this only blocks new registrations.
+    pub fn remove_from_allowlist(ctx: Context<UpdateAllowlist>, wallet: Pubkey) -> Result<()> {
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.wallets.retain(|w| w != &wallet);
+        msg!("Removed {} from allowlist", wallet);
+        Ok(())
+    }
+
+    /// Create the arena's talent table override, starting from the compiled values.
+    pub fn init_talent_tables(ctx: Context<InitTalentTables>) -> Result<()> {
+        let tables = &mut ctx.accounts.talent_tables;
+        tables.arena = ctx.accounts.arena.key();
+        tables.values = TalentTableValues::default();
+        msg!("Talent tables initialized for arena {}", tables.arena);
+        Ok(())
+    }
+
+    pub fn set_talent_tables(ctx: Context<SetTalentTables>, values: TalentTableValues) -> Result<()> {
+        values.validate()?;
+        ctx.accounts.talent_tables.values = values;
+        msg!("Talent tables updated by {}", ctx.accounts.authority.key());
+        Ok(())
+    }
+
+    /// Start the battle-royale shrink: from now on `zone_tick` damages players
+    /// the server has marked outside the safe zone.
+    pub fn start_zone_shrink(ctx: Context<SetArenaActive>, damage_per_sec: u16) -> Result<()> {
+        require!(damage_per_sec > 0, CombatError::InvalidConfig);
+        let arena = &mut ctx.accounts.arena;
+        arena.zone = ZoneState {
+            shrink_started_at: checked_now()?,
+            damage_per_sec,
+        };
+        msg!("Zone shrink started at {} ({} dmg/s)", arena.zone.shrink_started_at, damage_per_sec);
+        Ok(())
+    }
+
+    /// Repair a player whose stored talent ranks exceed their slot's max.
+    pub fn sanitize_player(ctx: Context<SanitizePlayer>) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        let clamped = sanitize_talent_ranks(player);
+        msg!("Player {} sanitized ({} slots clamped)", player.wallet, clamped);
+        Ok(())
+    }
+
+    /// Recompute a player's comeback buff against `top_player`, the arena's
+    /// highest-level player as tracked by the server. HP is trimmed if the new
+    /// effective max is lower.
+    pub fn refresh_comeback(ctx: Context<RefreshComeback>) -> Result<()> {
+        let config = &ctx.accounts.arena.config;
+        let top_level = calc_level(ctx.accounts.top_player.xp);
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+
+        let level = calc_level(player.xp);
+        player.comeback_bps = comeback_bps(level, top_level, config) as u16;
+        let eff_max = effective_max_health(player, config);
+        player.health = (player.health as u32).min(eff_max) as u16;
+        msg!("Comeback: {} level {} vs top {} -> +{} bps", player.wallet, level, top_level, player.comeback_bps);
+        Ok(())
+    }
+
+    /// Server-side zone membership for one player.
+    pub fn set_in_zone(ctx: Context<SetInZone>, in_zone: bool) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        // Time spent inside never turns into damage on the next tick
+        if in_zone != player.in_zone {
+            player.last_zone_tick_at = checked_now()?;
+        }
+        player.in_zone = in_zone;
+        Ok(())
+    }
+
+    /// Apply zone damage to up to ZONE_TICK_MAX_PLAYERS players in
+    /// `remaining_accounts`. A lethal tick goes through the normal death path;
+    /// in elimination mode, the last listed survivor wins once one remains.
+    pub fn zone_tick<'a>(ctx: Context<'_, '_, 'a, 'a, ZoneTick<'a>>) -> Result<()> {
+        require!(
+            !ctx.remaining_accounts.is_empty() && ctx.remaining_accounts.len() <= ZONE_TICK_MAX_PLAYERS,
+            CombatError::InvalidBatchSize
+        );
+        let arena = &mut ctx.accounts.arena;
+        require!(arena.is_active, CombatError::ArenaInactive);
+        let now = checked_now()?;
+        let zone = arena.zone;
+
+        let mut survivor = None;
+        for acct in ctx.remaining_accounts.iter() {
+            let mut player: Account<'a, PlayerState> = Account::try_from(acct)?;
+            require_keys_eq!(player.arena, arena.key(), CombatError::WrongArena);
+            if !player.initialized || !player.is_alive || player.is_dummy {
+                continue;
+            }
+            let eff_max = effective_max_health(&player, &arena.config);
+            let damage = zone_damage(&player, &zone, eff_max, now);
+            player.last_zone_tick_at = now;
+            if damage > 0 {
+                bump_event_seq(arena);
+                let killed = player.health <= damage;
+                if killed {
+                    record_death(&mut player, arena, Pubkey::default(), now);
+                } else {
+                    player.health -= damage;
+                }
+                player.total_damage_taken = player.total_damage_taken.saturating_add(damage as u64);
+                emit!(ZoneDamageEvent {
+                    wallet: player.wallet,
+                    damage,
+                    killed,
+                });
+                msg!("ZONE: {} -{} HP{}", player.wallet, damage, if killed { " (killed)" } else { "" });
+            }
+            if player.is_alive {
+                survivor = Some(player.wallet);
+            }
+            player.exit(&crate::ID)?;
+        }
+
+        if arena.config.elimination_mode && arena.alive_count == 1 {
+            if let Some(winner) = survivor {
+                arena.is_active = false;
+                if arena.winner.is_none() {
+                    arena.winner = Some(winner);
+                }
+                emit!(EliminationWinnerEvent {
+                    arena: arena.key(),
+                    winner,
+                    kills: 0,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Practice arenas only: restore an alive player to full HP without
+    /// touching XP, talents or cooldowns.
+    pub fn full_heal(ctx: Context<FullHeal>) -> Result<()> {
+        let config = &ctx.accounts.arena.config;
+        let player = &mut ctx.accounts.player_state;
+        require!(config.practice, CombatError::PracticeOnly);
+        require!(player.initialized, CombatError::NotInitialized);
+        require!(player.is_alive, CombatError::AttackerDead);
+
+        let eff_max = effective_max_health(player, config);
+        let healed = eff_max.saturating_sub(player.health as u32);
+        player.health = eff_max as u16;
+
+        emit!(FullHealEvent {
+            wallet: player.wallet,
+            healed: healed as u16,
+            health: player.health,
+        });
+        msg!("FULL HEAL: {} +{} HP ({})", player.wallet, healed, player.health);
+        Ok(())
+    }
+
+    /// Cosmetic only: the wallet picks the skin the client renders for it.
+    pub fn set_skin(ctx: Context<SetSkin>, skin_id: u16) -> Result<()> {
+        let player = &mut ctx.accounts.player_state;
+        require!(player.initialized, CombatError::NotInitialized);
+        let previous = player.skin_id;
+        player.skin_id = skin_id;
+        emit!(SkinChangedEvent {
+            wallet: player.wallet,
+            previous,
+            skin_id,
+        });
+        msg!("SKIN: {} {} -> {}", player.wallet, previous, skin_id);
+        Ok(())
+    }
+
+    /// Challenge `opponent` to a duel. One open duel per challenger; it starts
+    /// once the opponent calls `accept_duel`.
+    pub fn challenge_duel(ctx: Context<ChallengeDuel>, opponent: Pubkey) -> Result<()> {
+        let challenger = ctx.accounts.challenger.key();
+        require_keys_neq!(challenger, opponent, CombatError::DuelUnavailable);
+        let duel = &mut ctx.accounts.duel;
+        duel.arena = ctx.accounts.arena.key();
+        duel.challenger = challenger;
+        duel.opponent = opponent;
+        duel.accepted = false;
+        duel.winner = None;
+        msg!("Duel challenge: {} -> {}", challenger, opponent);
+        Ok(())
+    }
+
+    /// The opponent takes the challenge; both players are locked into the duel.
+    pub fn accept_duel(ctx: Context<AcceptDuel>) -> Result<()> {
+        let duel = &mut ctx.accounts.duel;
+        let challenger_state = &mut ctx.accounts.challenger_state;
+        let opponent_state = &mut ctx.accounts.opponent_state;
+        require!(!duel.accepted && duel.winner.is_none(), CombatError::DuelUnavailable);
+        require!(challenger_state.initialized && opponent_state.initialized, CombatError::NotInitialized);
+        require!(
+            challenger_state.arena == duel.arena && opponent_state.arena == duel.arena,
+            CombatError::WrongArena
+        );
+        require!(challenger_state.is_alive && opponent_state.is_alive, CombatError::DuelUnavailable);
+        require!(
+            challenger_state.duel == Pubkey::default() && opponent_state.duel == Pubkey::default(),
+            CombatError::DuelUnavailable
+        );
+
+        duel.accepted = true;
+        challenger_state.duel = duel.key();
+        opponent_state.duel = duel.key();
+        emit!(DuelStartedEvent {
+            duel: duel.key(),
+            challenger: duel.challenger,
+            opponent: duel.opponent,
+        });
+        msg!("Duel started: {} vs {}", duel.challenger, duel.opponent);
+        Ok(())
+    }
+
+    /// Either duelist closes the duel: withdraws or declines an open challenge,
+    /// or cleans up a decided one. A running duel cannot be abandoned; it ends
+    /// with a kill. Both players are released and the rent goes back to the
+    /// challenger.
+    pub fn close_duel(ctx: Context<CloseDuel>) -> Result<()> {
+        let duel = &ctx.accounts.duel;
+        require!(!duel.accepted || duel.winner.is_some(), CombatError::DuelRestricted);
+        let key = duel.key();
+        for player in [&mut ctx.accounts.challenger_state, &mut ctx.accounts.opponent_state] {
+            if player.duel == key {
+                player.duel = Pubkey::default();
+            }
+        }
+        msg!("Duel {} closed (winner: {:?})", key, ctx.accounts.duel.winner);
+        Ok(())
+    }
+}
+
+// ─── Talent prerequisite chain ───────────────────────────────────────────────
+// Each tree: T1 → T2 → T3 → T4 → T5(capstone)
+// Returns the talent slot that must have ≥1 rank before this talent can be allocated.
+fn talent_prerequisite(talent_id: u8) -> Option<u8> {
+    match talent_id {
+        // Tank: 0 → 1 → 2 → 3 → 4
+        0 => None,
+        1 => Some(0),
+        2 => Some(1),
+        3 => Some(2),
+        4 => Some(3),
+        // Firepower: 5 → 6 → 7 → 8 → 9
+        5 => None,
+        6 => Some(5),
+        7 => Some(6),
+        8 => Some(7),
+        9 => Some(8),
+        // Brawler: 10 → 11 → 12 → 13 → 14
+        10 => None,
+        11 => Some(10),
+        12 => Some(11),
+        13 => Some(12),
+        14 => Some(13),
+        // Mass Damage: 15 → 16 → 19 → 18 → 17
+        // (ricochet → counterAttack → focusFire → nova → chainLightning)
+        15 => None,
+        16 => Some(15),
+        19 => Some(16),
+        18 => Some(19),
+        17 => Some(18),
+        // Blood Thirst: 20 → 21 → 22 → 23 → 24
+        20 => None,
+        21 => Some(20),
+        22 => Some(21),
+        23 => Some(22),
+        24 => Some(23),
+        _ => None,
+    }
+}
+
+// ─── Account Structs ─────────────────────────────────────────────────────────
+
+#[account]
+pub struct Arena {
+    pub authority: Pubkey,
+    pub player_count: u32,
+    pub total_kills: u64,
+    pub is_active: bool,
+    pub config: ArenaConfig,
+    pub winner: Option<Pubkey>,
+    /// Folded into the PDA seeds so several matches can run side by side.
+    pub arena_id: u16,
+    /// Next chunk `commit_state_chunked` expects; a crashed settlement resumes here.
+    pub commit_chunk_next: u16,
+    /// Chunk count of the settlement in progress, 0 when none is running.
+    pub commit_chunk_total: u16,
+    /// HP removed from players by every attack in this arena (dummies excluded).
+    /// Balance telemetry alongside `total_kills`; pausing does not reset it.
+    pub total_damage_dealt: u128,
+    /// Registered non-dummy players currently alive. Drives elimination mode.
+    pub alive_count: u32,
+    /// Players who have scored at least one kill this session.
+    pub unique_killers: u32,
+    /// Battle-royale shrink; inert until `start_zone_shrink`.
+    pub zone: ZoneState,
+    /// Time of the last arena commit (0 before the first).
+    pub last_commit_at: i64,
+    /// Attacks resolved since the last arena commit; a proxy for dirty players.
+    pub dirty_since_commit: u32,
+    /// PHASE_LIVE, PHASE_WARMUP or PHASE_ENDED. Attacks deal no damage in warmup.
+    pub phase: u8,
+    /// Player with a price on their head (default = no bounty)
+    pub bounty_target: Pubkey,
+    /// Extra XP paid to whoever kills `bounty_target`, once
+    pub bounty_xp: u64,
+    /// Current session, starting at 1; ending a session (`end_session` or an
+    /// undelegating final chunk) and `force_reset_arena` open the next one.
+    /// Players remember the session of their last kill so `unique_killers`
+    /// counts each of them once per session.
+    pub session_id: u32,
+    /// Gameplay state changes in this arena so far (attacks, deaths, respawns,
+    /// forfeits, resets). Commit receipts report it so the indexer can mark
+    /// everything up to that point finalized.
+    pub event_seq: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct ZoneState {
+    /// When the shrink began; 0 while the zone is inert
+    pub shrink_started_at: i64,
+    /// Base damage per second outside the zone, before the ramp
+    pub damage_per_sec: u16,
+}
+
+/// The fields every Arena layout has started with. Used to carry state across
+/// `migrate_arena` when the account predates the current layout.
+#[derive(AnchorDeserialize)]
+struct ArenaHeader {
+    authority: Pubkey,
+    player_count: u32,
+    total_kills: u64,
+    is_active: bool,
+}
+
+/// Per-arena tuning set by the arena authority. Defaults reproduce the
+/// original hardcoded behaviour.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ArenaConfig {
+    /// Upper bound on `hit_count` accepted by a single `process_attack`.
+    pub max_hit_count: u16,
+    /// Incoming damage reduction applied to everyone after Armor (bps, 0 = off).
+    pub global_damage_reduction_bps: u16,
+    /// Arena deactivates once `total_kills` reaches this (0 = no goal).
+    pub kill_goal: u64,
+    /// Arena deactivates once a single player's `kills` reaches this (0 = off).
+    pub player_kill_goal: u64,
+    /// Resolution order of attacker Lifesteal and victim Counter Attack.
+    pub combat_order: CombatOrder,
+    /// Points needed in a single tree to activate its synergy bonus (0 = off).
+    pub tree_bonus_threshold: u16,
+    /// Synergy bonus per tree in bps: [Tank HP, Firepower dmg, Brawler HP, Mass Damage dmg, Blood XP].
+    pub tree_bonus_bps: [u16; 5],
+    /// Fraction of effective max HP restored on respawn (bps, 10000 = full).
+    pub respawn_hp_bps: u16,
+    /// How kill XP grows with victim level; multipliers apply after the curve.
+    pub kill_xp_curve: KillXpCurve,
+    /// Final multiplier on a volley's total damage (bps, 10000 = neutral).
+    pub difficulty_bps: u16,
+    /// How percentage damage bonuses combine in compute_hit_damage.
+    pub damage_model: DamageModel,
+    /// Victim HP fraction at or below which Execute applies (bps, 5000 = 50%).
+    pub execute_threshold_bps: u16,
+    /// XP lost per kill of `current_streak` when forfeiting; 0 disables the rage-quit penalty
+    pub rage_quit_xp_per_streak: u64,
+    /// Streak at which the rage-quit penalty starts to apply
+    pub rage_quit_min_streak: u16,
+    /// How many of the 5 capstone talents a player may hold at once
+    pub max_capstones: u8,
+    /// Bonus damage against `last_killed_by`; 0 disables revenge
+    pub revenge_bps: u16,
+    /// Last man standing: dead players stay out and the final survivor wins
+    pub elimination_mode: bool,
+    /// Berserker ramps linearly from 0 at 33% HP to its full bonus at 0 HP instead of switching on at 33%
+    pub berserker_smooth: bool,
+    /// Damage cut for a taunted player hitting anyone but their taunter; 0 disables taunts (team modes only)
+    pub taunt_reduction_bps: u16,
+    /// A death halves `current_streak` instead of clearing it, at most once per STREAK_GRACE_COOLDOWN_SECS
+    pub streak_grace: bool,
+    /// Kills, XP grants and point claims set health/attack level to the XP level; when off, levels only move through `upgrade_stat`
+    pub auto_level: bool,
+    /// Resolution of the damage pipeline in units per 1.0 game damage. Above DAMAGE_SCALE the intermediate stages keep extra precision and the hit is scaled back to HP units at the end
+    pub damage_scale: u16,
+    /// Execute also grants bonus kill XP (EXECUTE_KILL_XP_BPS) for kills on victims already in execute range
+    pub execute_kill_xp: bool,
+    /// Flat base damage per attack level above 1, on top of the +5 attack_power from upgrade_stat; 0 keeps damage talent-driven
+    pub attack_power_per_level: u16,
+    /// Global XP scalar (10000 = 1x) applied last to every combat XP gain, point claim and grant_xp
+    pub xp_multiplier_bps: u32,
+    /// Diminish kill XP for repeatedly killing the same victim (see ANTI_FARM_XP_BPS)
+    pub anti_farm: bool,
+    /// Base regen in HP/sec for players out of combat (see `tick_regen`); 0 disables
+    pub out_of_combat_regen_per_sec: u16,
+    /// Seconds without dealing or taking a hit before out-of-combat regen starts
+    pub out_of_combat_secs: u16,
+    /// How Critical Strike resolves: expected value, real rolls, or rolls with a capped burst
+    pub crit_model: CritModel,
+    /// Share of effective max HP restored by `use_heal` (bps); 0 disables the heal
+    pub heal_bps: u16,
+    /// Cooldown of `use_heal` in seconds
+    pub heal_cooldown_secs: u16,
+    /// Minimum level (calc_level) before any capstone slot can take ranks; 0 leaves capstones ungated
+    pub capstone_min_level: u8,
+    /// Scale on every player's base max HP before Iron Skin and synergy (bps, 10000 = normal, 5000 = glass cannon)
+    pub hp_scale_bps: u16,
+    /// XP losses that drop a player's level refund over-spent talent ranks (TalentRefundEvent) instead of being refused or floored at the level
+    pub refund_on_overflow: bool,
+    /// Ceiling on the combined percentage damage bonus of one hit (Heavy Hitter, synergy, Berserker, Revenge, Crit, Execute) in bps over base; 0 leaves it uncapped
+    pub max_offensive_bps: u16,
+    /// Invite-only: `register_player` only accepts wallets on the arena's Allowlist
+    pub private: bool,
+    /// Cut kill XP when the attacker out-levels the victim by more than `anti_stomp_gap`
+    pub anti_stomp: bool,
+    /// Level gap (attacker minus victim) tolerated before anti-stomp applies
+    pub anti_stomp_gap: u8,
+    /// The Tank capstone (slot 4) also grants Last Stand: survive the first lethal hit per life at 1 HP
+    pub last_stand: bool,
+    /// Adjustment (bps, signed) to both damage and max HP for players holding two or more capstones; 0 disables
+    pub dual_capstone_bps: i16,
+    /// Victims earn XP_PER_DEATH on every death; competitive modes turn this off
+    pub death_xp_enabled: bool,
+    /// Practice arena: `full_heal` tops players up to max HP on demand
+    pub practice: bool,
+    /// Scales Iron Skin's max-HP bonus, bps (10000 = table values)
+    pub iron_skin_scale_bps: u16,
+    /// Scales Armor's damage reduction, bps (10000 = table values)
+    pub armor_scale_bps: u16,
+    /// Limited-build cap on total talent points at any level; 0 = level-based budget only
+    pub max_total_points: u16,
+    /// Rubber-banding: players far below the top player's level get extra max HP
+    pub comeback_buff: bool,
+    /// Levels a player may trail the top player before the comeback buff starts
+    pub comeback_level_gap: u8,
+    /// Per-hit damage share (bps) by the server's distance bucket; all 10000 = no falloff
+    pub falloff_table: [u16; FALLOFF_BUCKETS],
+    /// Classic mode: no talent allocation, and damage/max HP ignore any talent ranks
+    pub talents_disabled: bool,
+    /// Level new players start at (late-start events); must equal calc_level(start_xp)
+    pub start_level: u8,
+    /// XP new players start with
+    pub start_xp: u64,
+    /// Hits beyond this count with diminishing returns (2*sqrt(cap*hits) - cap); at or above max_hit_count every hit counts fully
+    pub soft_hit_cap: u16,
+    /// Armor (slot 0) also grants Second Wind: respawn with RESPAWN_HP_TALENT_BPS more of max HP
+    pub second_wind: bool,
+    /// Flat armor penetration, bps: every attacker ignores this much of the victim's Armor; 0 disables
+    pub armor_pen_bps: u16,
+    /// With auto_level, also re-derive max HP and attack from the new levels on the spot; off leaves them for the next respawn or upgrade
+    pub auto_level_sync: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CombatOrder {
+    LifestealBeforeCounter,
+    CounterBeforeLifesteal,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum KillXpCurve {
+    /// base + (level - 1) * per_level
+    Linear,
+    /// base + sqrt((level - 1) * KILL_XP_SQRT_PIVOT) * per_level
+    Sqrt,
+    /// Linear up to KILL_XP_CAP_LEVEL, flat after
+    Capped,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CritModel {
+    /// Every hit gets the average crit bonus (`crit_expected_bps`)
+    ExpectedValue,
+    /// Each hit rolls `crit_chance_bps` for the full `crit_mult_bps`
+    Real,
+    /// Rolled like Real, but one crit adds at most CRIT_BURST_CAP_BPS
+    CappedBurst,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum DamageModel {
+    /// Heavy Hitter, synergy, Berserker, Crit and Execute multiply in sequence
+    Multiplicative,
+    /// The same bonuses are summed and applied as a single multiplier
+    Additive,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        Self {
+            max_hit_count: DEFAULT_MAX_HIT_COUNT,
+            global_damage_reduction_bps: 0,
+            kill_goal: 0,
+            player_kill_goal: 0,
+            combat_order: CombatOrder::LifestealBeforeCounter,
+            tree_bonus_threshold: 0,
+            tree_bonus_bps: [0; 5],
+            respawn_hp_bps: 10000,
+            kill_xp_curve: KillXpCurve::Linear,
+            difficulty_bps: 10000,
+            damage_model: DamageModel::Multiplicative,
+            execute_threshold_bps: 5000,
+            rage_quit_xp_per_streak: 0,
+            rage_quit_min_streak: 3,
+            max_capstones: 2,
+            revenge_bps: 0,
+            elimination_mode: false,
+            berserker_smooth: false,
+            taunt_reduction_bps: 0,
+            streak_grace: false,
+            auto_level: true,
+            damage_scale: DAMAGE_SCALE as u16,
+            execute_kill_xp: false,
+            attack_power_per_level: 0,
+            xp_multiplier_bps: 10000,
+            anti_farm: false,
+            out_of_combat_regen_per_sec: 0,
+            out_of_combat_secs: OUT_OF_COMBAT_SECS,
+            crit_model: CritModel::ExpectedValue,
+            heal_bps: 0,
+            heal_cooldown_secs: HEAL_COOLDOWN_SECS,
+            capstone_min_level: 0,
+            hp_scale_bps: 10000,
+            refund_on_overflow: false,
+            max_offensive_bps: 0,
+            private: false,
+            anti_stomp: false,
+            anti_stomp_gap: 10,
+            last_stand: false,
+            dual_capstone_bps: 0,
+            death_xp_enabled: true,
+            practice: false,
+            iron_skin_scale_bps: 10000,
+            armor_scale_bps: 10000,
+            max_total_points: 0,
+            comeback_buff: false,
+            comeback_level_gap: 10,
+            falloff_table: [10000; FALLOFF_BUCKETS],
+            talents_disabled: false,
+            start_level: 1,
+            start_xp: 0,
+            soft_hit_cap: DEFAULT_MAX_HIT_COUNT,
+            second_wind: false,
+            armor_pen_bps: 0,
+            auto_level_sync: false,
+        }
+    }
+}
+
+impl ArenaConfig {
+    fn validate(&self) -> Result<()> {
+        require!(self.max_hit_count > 0, CombatError::InvalidConfig);
+        require!(self.global_damage_reduction_bps <= 10000, CombatError::InvalidConfig);
+        require!(self.tree_bonus_threshold <= 23, CombatError::InvalidConfig);
+        require!(self.tree_bonus_bps.iter().all(|&b| b <= 10000), CombatError::InvalidConfig);
+        require!(self.respawn_hp_bps > 0 && self.respawn_hp_bps <= 10000, CombatError::InvalidConfig);
+        require!(self.difficulty_bps > 0, CombatError::InvalidConfig);
+        require!(self.execute_threshold_bps <= 10000, CombatError::InvalidConfig);
+        require!(self.max_capstones <= 5, CombatError::InvalidConfig);
+        require!(self.revenge_bps <= 10000, CombatError::InvalidConfig);
+        require!(self.taunt_reduction_bps <= 10000, CombatError::InvalidConfig);
+        require!(
+            self.damage_scale as u32 >= DAMAGE_SCALE
+                && self.damage_scale as u32 <= 10 * DAMAGE_SCALE
+                && self.damage_scale as u32 % DAMAGE_SCALE == 0,
+            CombatError::InvalidConfig
+        );
+        require!(self.attack_power_per_level <= 50, CombatError::InvalidConfig);
+        require!(self.xp_multiplier_bps > 0, CombatError::InvalidConfig);
+        require!(self.heal_bps <= 10000, CombatError::InvalidConfig);
+        require!(self.hp_scale_bps > 0, CombatError::InvalidConfig);
+        require!((-5000..=5000).contains(&self.dual_capstone_bps), CombatError::InvalidConfig);
+        require!(self.iron_skin_scale_bps <= 20000, CombatError::InvalidConfig);
+        require!(self.armor_scale_bps <= 20000, CombatError::InvalidConfig);
+        require!(self.falloff_table.iter().all(|&bps| bps > 0 && bps <= 10000), CombatError::InvalidConfig);
+        require!(self.start_level >= 1 && calc_level(self.start_xp) == self.start_level, CombatError::InvalidConfig);
+        require!(self.soft_hit_cap > 0, CombatError::InvalidConfig);
+        require!(self.armor_pen_bps <= 10000, CombatError::InvalidConfig);
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default, InitSpace)]
+pub struct PlayerState {
+    pub wallet: Pubkey,
+    pub health: u16,
+    pub max_health: u16,
+    pub attack_power: u16,
+    pub xp: u64,
+    pub kills: u64,
+    pub deaths: u64,
+    pub health_level: u8,
+    pub attack_level: u8,
+    pub is_alive: bool,
+    pub respawn_at: i64,
+    pub initialized: bool,
+    pub talent_iron_skin: u8,
+    pub talent_heavy_hitter: u8,
+    pub talent_regeneration: u8,
+    pub talent_lifesteal: u8,
+    pub talent_armor: u8,
+    pub talent_swift: u8,
+    pub talent_rapid_fire: u8,
+    pub talent_evasion: u8,
+    pub talent_quick_respawn: u8,
+    pub talent_momentum: u8,
+    pub talent_weakspot: u8,
+    pub talent_critical_strike: u8,
+    pub talent_focus_fire: u8,
+    pub talent_multi_shot: u8,
+    pub talent_dual_cannon: u8,
+    pub talent_deflect: u8,
+    pub talent_absorb: u8,
+    pub talent_last_stand: u8,
+    pub talent_cloak: u8,
+    pub talent_dash: u8,
+    pub talent_rampage: u8,
+    pub talent_homing: u8,
+    pub talent_ricochet: u8,
+    pub talent_deathbomb: u8,
+    pub talent_frenzy: u8,
+    pub manual_build: bool,
+    pub is_dummy: bool,
+    pub total_damage_taken: u64,
+    /// Superseded by `ability_cooldowns[ABILITY_ULT]`; kept for the account layout
+    pub ult_ready_at: i64,
+    pub out_of_match: bool,
+    pub top_damager: Pubkey,
+    pub top_damage: u16,
+    pub current_streak: u16,
+    /// Wallet that last killed this player; hitting them back earns the arena's revenge bonus
+    pub last_killed_by: Pubkey,
+    /// Player this one is taunted by until `taunt_until`
+    pub taunted_by: Pubkey,
+    pub taunt_until: i64,
+    /// When streak grace last halved instead of reset this player's streak (0 = never)
+    pub streak_grace_used_at: i64,
+    /// Superseded by `ability_cooldowns[ABILITY_DASH]`; kept for the account layout
+    pub dash_ready_at: i64,
+    /// Superseded by `kill_session`; kept for the account layout
+    pub got_kill_this_session: bool,
+    /// Ring buffer of recent victims for the anti-farm check; `recent_victims_head` is the next slot
+    pub recent_victims: [Pubkey; ANTI_FARM_SLOTS],
+    pub recent_victims_head: u8,
+    /// Time of the last kill recorded in `recent_victims`
+    pub last_kill_at: i64,
+    /// Last time this player dealt or took a hit
+    pub last_combat_at: i64,
+    /// Last `tick_regen` that ran for this player
+    pub last_regen_at: i64,
+    /// Superseded by `ability_cooldowns[ABILITY_HEAL]`; kept for the account layout
+    pub heal_ready_at: i64,
+    /// Damage taken since the last respawn, overkill included (LIFE_DAMAGE_CAP_BPS)
+    pub damage_taken_this_life: u16,
+    /// Inside the safe zone, as marked by the server; only players outside take zone damage
+    pub in_zone: bool,
+    /// Last `zone_tick` that covered this player
+    pub last_zone_tick_at: i64,
+    /// Last Stand already saved this life
+    pub last_stand_used: bool,
+    /// Hits land for 0 damage until this time (Last Stand)
+    pub invulnerable_until: i64,
+    /// Dead or forfeited and waiting on `respawn_player`; `respawn_at` says when it is allowed
+    pub respawn_pending: bool,
+    /// Ready-at time per active ability, indexed by the ABILITY_* slots; 0 = ready
+    pub ability_cooldowns: [i64; ABILITY_SLOTS],
+    /// Cosmetic skin shown by the client; never read by combat (0 = default)
+    pub skin_id: u16,
+    /// Comeback max-HP bonus in bps, set by `refresh_comeback`; used only while the arena's comeback_buff is on
+    pub comeback_bps: u16,
+    /// Accepted Duel this player is locked into (default = none)
+    pub duel: Pubkey,
+    /// Tombstone: no hits and no respawn before this time (DEATH_GRACE_SECS after a death)
+    pub death_grace_until: i64,
+    /// Arena this player registered in; arena-scoped instructions only accept that arena
+    pub arena: Pubkey,
+    /// Arena session of this player's last kill (0 = none); a first kill in a newer session counts toward `unique_killers`
+    pub kill_session: u32,
+    /// Layout version (PLAYER_SCHEMA_VERSION). Always the last byte of the
+    /// account; new fields go above it.
+    pub schema_version: u8,
+}
+
+impl PlayerState {
+    /// The ability in cooldown slot `idx` (an ABILITY_* constant) can be used at `now`.
+    pub fn is_ability_ready(&self, idx: usize, now: i64) -> bool {
+        now >= self.ability_cooldowns[idx]
+    }
+
+    /// Put the ability in cooldown slot `idx` on cooldown until `until`.
+    pub fn set_ability_cooldown(&mut self, idx: usize, until: i64) {
+        self.ability_cooldowns[idx] = until;
+    }
+
+    pub fn get_talent(&self, id: u8) -> u8 {
+        match id {
+            0 => self.talent_iron_skin,
+            1 => self.talent_heavy_hitter,
+            2 => self.talent_regeneration,
+            3 => self.talent_lifesteal,
+            4 => self.talent_armor,
+            5 => self.talent_swift,
+            6 => self.talent_rapid_fire,
+            7 => self.talent_evasion,
+            8 => self.talent_quick_respawn,
+            9 => self.talent_momentum,
+            10 => self.talent_weakspot,
+            11 => self.talent_critical_strike,
+            12 => self.talent_focus_fire,
+            13 => self.talent_multi_shot,
+            14 => self.talent_dual_cannon,
+            15 => self.talent_deflect,
+            16 => self.talent_absorb,
+            17 => self.talent_last_stand,
+            18 => self.talent_cloak,
+            19 => self.talent_dash,
+            20 => self.talent_rampage,
+            21 => self.talent_homing,
+            22 => self.talent_ricochet,
+            23 => self.talent_deathbomb,
+            24 => self.talent_frenzy,
+            _ => 0,
+        }
+    }
+
+    pub fn set_talent(&mut self, id: u8, val: u8) {
+        match id {
+            0 => self.talent_iron_skin = val,
+            1 => self.talent_heavy_hitter = val,
+            2 => self.talent_regeneration = val,
+            3 => self.talent_lifesteal = val,
+            4 => self.talent_armor = val,
+            5 => self.talent_swift = val,
+            6 => self.talent_rapid_fire = val,
+            7 => self.talent_evasion = val,
+            8 => self.talent_quick_respawn = val,
+            9 => self.talent_momentum = val,
+            10 => self.talent_weakspot = val,
+            11 => self.talent_critical_strike = val,
+            12 => self.talent_focus_fire = val,
+            13 => self.talent_multi_shot = val,
+            14 => self.talent_dual_cannon = val,
+            15 => self.talent_deflect = val,
+            16 => self.talent_absorb = val,
+            17 => self.talent_last_stand = val,
+            18 => self.talent_cloak = val,
+            19 => self.talent_dash = val,
+            20 => self.talent_rampage = val,
+            21 => self.talent_homing = val,
+            22 => self.talent_ricochet = val,
+            23 => self.talent_deathbomb = val,
+            24 => self.talent_frenzy = val,
+            _ => {}
+        }
+    }
+
+    pub fn points_in_tree(&self, tree: usize) -> u16 {
+        TREE_SLOTS[tree].iter().map(|&id| self.get_talent(id) as u16).sum()
+    }
+
+    pub fn total_talent_points_spent(&self) -> u16 {
+        (self.talent_iron_skin as u16)
+            + (self.talent_heavy_hitter as u16)
+            + (self.talent_regeneration as u16)
+            + (self.talent_lifesteal as u16)
+            + (self.talent_armor as u16)
+            + (self.talent_swift as u16)
+            + (self.talent_rapid_fire as u16)
+            + (self.talent_evasion as u16)
+            + (self.talent_quick_respawn as u16)
+            + (self.talent_momentum as u16)
+            + (self.talent_weakspot as u16)
+            + (self.talent_critical_strike as u16)
+            + (self.talent_focus_fire as u16)
+            + (self.talent_multi_shot as u16)
+            + (self.talent_dual_cannon as u16)
+            + (self.talent_deflect as u16)
+            + (self.talent_absorb as u16)
+            + (self.talent_last_stand as u16)
+            + (self.talent_cloak as u16)
+            + (self.talent_dash as u16)
+            + (self.talent_rampage as u16)
+            + (self.talent_homing as u16)
+            + (self.talent_ricochet as u16)
+            + (self.talent_deathbomb as u16)
+            + (self.talent_frenzy as u16)
+    }
+}
+
+#[account]
+pub struct ControlPoint {
+    pub arena: Pubkey,
+    pub owner: Pubkey,
+    pub held_since: i64,
+    pub last_claim_at: i64,
+}
+
+/// Return data of `process_attack`: one hit's damage after each stage of
+/// `compute_hit_damage`. `after_vitality` already includes the damage cap under
+/// the multiplicative model; `final_damage` is after the arena-wide reduction,
+/// any taunt penalty and the 1-damage floor. Every stage before `final_damage`
+/// is in the arena's `damage_scale`; `final_damage` is back in HP units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct DamageBreakdown {
+    pub base: u32,
+    pub after_heavy_hitter: u32,
+    pub after_synergy: u32,
+    pub after_berserker: u32,
+    pub after_revenge: u32,
+    pub after_vitality: u32,
+    pub after_crit: u32,
+    pub after_execute: u32,
+    pub after_armor: u32,
+    pub final_damage: u16,
+    /// A rolled crit landed on at least one hit (always false under
+    /// CritModel::ExpectedValue). The stages above are an ordinary hit's.
+    pub crit: bool,
+    /// Hits of the volley whose own crit roll landed
+    pub crit_hits: u16,
+    /// Damage of one crit hit, after falloff; 0 when none landed
+    pub crit_damage: u16,
+}
+
+/// Return data of the commit instructions. The indexer treats the arena's
+/// state changes up to `event_seq` as finalized by this commit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CommitReceipt {
+    /// Validator clock when the commit was issued
+    pub committed_at: i64,
+    /// The arena's `event_seq` at commit time
+    pub event_seq: u64,
+    /// Accounts handed to the magic program, the arena included when committed
+    pub accounts: u32,
+}
+
+/// Return data of `simulate_attack`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AttackPreview {
+    pub damage_per_hit: u16,
+    pub total_damage: u16,
+    pub would_kill: bool,
+    pub kill_xp_if_killed: u64,
+}
+
+/// Return data of `next_commit_recommended_at`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CommitSchedule {
+    pub recommended_at: i64,
+    pub dirty_since_commit: u32,
+}
+
+/// Return data of `get_arena_stats`. Average damage per kill is
+/// `total_damage_dealt / total_kills`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ArenaStats {
+    pub player_count: u32,
+    pub total_kills: u64,
+    pub total_damage_dealt: u128,
+    pub is_active: bool,
+    pub unique_killers: u32,
+}
+
+/// Return data of `get_player_summary`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PlayerSummary {
+    pub level: u8,
+    pub talent_points: u16,
+    pub talent_points_spent: u16,
+    pub effective_max_health: u32,
+    /// Bit N set when the synergy for tree N is active.
+    pub active_synergies: u8,
+    /// Critical Strike chance at the player's rank (0 without the talent)
+    pub crit_chance_bps: u32,
+    /// Critical Strike damage multiplier at the player's rank (0 without the talent)
+    pub crit_mult_bps: u32,
+}
+
+/// Return data of `get_respawn_countdown`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RespawnCountdown {
+    pub seconds: u32,
+    pub is_alive: bool,
+}
+
+/// Return data of `can_allocate_talent`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TalentAllocationCheck {
+    pub allowed: bool,
+    /// CombatError variant index when not allowed
+    pub reason: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct QueueEntry {
+    pub wallet: Pubkey,
+    pub level: u8,
+    pub enqueued_at: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MatchQueue {
+    pub arena: Pubkey,
+    #[max_len(MATCH_QUEUE_MAX)]
+    pub entries: Vec<QueueEntry>,
+}
+
+impl MatchQueue {
+    /// Drop entries that have waited longer than MATCH_QUEUE_TIMEOUT_SECS.
+    fn evict_stale(&mut self, now: i64) {
+        self.entries.retain(|e| now - e.enqueued_at <= MATCH_QUEUE_TIMEOUT_SECS);
+    }
+}
+
+/// Return data of `pop_match`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MatchPair {
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+}
+
+/// Wallets allowed to register in a private arena (`ArenaConfig::private`).
+#[account]
+#[derive(InitSpace)]
+pub struct Allowlist {
+    pub arena: Pubkey,
+    #[max_len(ALLOWLIST_MAX)]
+    pub wallets: Vec<Pubkey>,
+}
+
+/// Per-rank bps of the damage talents, overriding the compiled tables for one
+/// arena so they can be rebalanced without a redeploy.
+#[account]
+#[derive(InitSpace)]
+pub struct TalentTables {
+    pub arena: Pubkey,
+    pub values: TalentTableValues,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct TalentTableValues {
+    pub armor_bps: [u32; 5],
+    pub heavy_hitter_bps: [u32; 5],
+    pub crit_expected_bps: [u32; 5],
+    pub execute_bps: [u32; 5],
+    pub vitality_strike_bps: [u32; 3],
+    pub berserker_dmg_bps: [u32; 3],
+    /// Rolled crit models (CritModel::Real, CappedBurst)
+    pub crit_chance_bps: [u32; 5],
+    pub crit_mult_bps: [u32; 5],
+    /// Armor penetration on top of the arena's `armor_pen_bps`
+    pub armor_pen_bps: u32,
+}
+
+impl Default for TalentTableValues {
+    fn default() -> Self {
+        Self {
+            armor_bps: ARMOR_BPS,
+            heavy_hitter_bps: HEAVY_HITTER_BPS,
+            crit_expected_bps: CRIT_EXPECTED_BPS,
+            execute_bps: EXECUTE_BPS,
+            vitality_strike_bps: VITALITY_STRIKE_BPS,
+            berserker_dmg_bps: BERSERKER_DMG_BPS,
+            crit_chance_bps: CRIT_CHANCE_BPS,
+            crit_mult_bps: CRIT_MULT_BPS,
+            armor_pen_bps: 0,
+        }
+    }
+}
+
+impl TalentTableValues {
+    /// Every table must be non-decreasing by rank and stay within sane bounds:
+    /// armor below 90% so hits always land, Vitality Strike at most 10% of max
+    /// HP, and the damage bonuses at most +200%. Crit chance is at most 100%
+    /// and a crit multiplier between 1x and 3x.
+    fn validate(&self) -> Result<()> {
+        let tables: [(&[u32], u32); 8] = [
+            (&self.armor_bps, 9000),
+            (&self.heavy_hitter_bps, 20000),
+            (&self.crit_expected_bps, 20000),
+            (&self.execute_bps, 20000),
+            (&self.vitality_strike_bps, 1000),
+            (&self.berserker_dmg_bps, 20000),
+            (&self.crit_chance_bps, 10000),
+            (&self.crit_mult_bps, 30000),
+        ];
+        for (table, max) in tables {
+            require!(
+                table.windows(2).all(|w| w[0] <= w[1]) && table.iter().all(|&v| v <= max),
+                CombatError::InvalidConfig
+            );
+        }
+        require!(self.crit_mult_bps[0] >= 10000, CombatError::InvalidConfig);
+        require!(self.armor_pen_bps <= 9000, CombatError::InvalidConfig);
+        Ok(())
+    }
+}
+
+/// A 1v1 between two players of one arena. Once accepted, both players can
+/// only attack each other until one of them lands a kill, which sets `winner`
+/// and ends the duel; either duelist then closes it for the rent.
+#[account]
+#[derive(InitSpace)]
+pub struct Duel {
+    pub arena: Pubkey,
+    pub challenger: Pubkey,
+    pub opponent: Pubkey,
+    pub accepted: bool,
+    pub winner: Option<Pubkey>,
+}
+
+// ─── Instruction Contexts ────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+#[instruction(arena_id: u16)]
+pub struct InitArena<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = ARENA_SPACE,
+        seeds = [ARENA_SEED, arena_id_seed(arena_id).as_ref()],
+        bump,
+    )]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct RegisterPlayer<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = PLAYER_STATE_SPACE,
+        seeds = [PLAYER_SEED, wallet.as_ref()],
+        bump,
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// Required only when the arena is private
+    #[account(seeds = [ALLOWLIST_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub allowlist: Option<Account<'info, Allowlist>>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterPlayersBatch<'info> {
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// Required only when the arena is private
+    #[account(seeds = [ALLOWLIST_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub allowlist: Option<Account<'info, Allowlist>>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct InitDummy<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = PLAYER_STATE_SPACE,
+        seeds = [DUMMY_SEED, wallet.as_ref()],
+        bump,
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(arena_id: u16)]
+pub struct DelegateArenaCtx<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Arena PDA to delegate
+    #[account(mut, del, seeds = [ARENA_SEED, arena_id_seed(arena_id).as_ref()], bump)]
+    pub arena: AccountInfo<'info>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct DelegatePlayerCtx<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Player PDA to delegate
+    #[account(mut, del, seeds = [PLAYER_SEED, wallet.as_ref()], bump)]
+    pub player_state: AccountInfo<'info>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct DelegateDummyCtx<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Dummy PDA to delegate
+    #[account(mut, del, seeds = [DUMMY_SEED, wallet.as_ref()], bump)]
+    pub player_state: AccountInfo<'info>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(challenger: Pubkey)]
+pub struct DelegateDuelCtx<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Duel PDA to delegate
+    #[account(mut, del, seeds = [DUEL_SEED, challenger.as_ref()], bump)]
+    pub duel: AccountInfo<'info>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(arena_id: u16)]
+pub struct JoinSession<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Arena PDA, delegated here unless it already is
+    #[account(mut, del, seeds = [ARENA_SEED, arena_id_seed(arena_id).as_ref()], bump)]
+    pub arena: AccountInfo<'info>,
+    /// CHECK: The payer's own player PDA
+    #[account(mut, del, seeds = [PLAYER_SEED, payer.key().as_ref()], bump)]
+    pub player_state: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessAttack<'info> {
+    // Boxed: two PlayerStates plus the arena would otherwise crowd the BPF stack
+    #[account(mut, seeds = [PLAYER_SEED, attacker.wallet.as_ref()], bump, constraint = attacker.arena == arena.key() @ CombatError::WrongArena)]
+    pub attacker: Box<Account<'info, PlayerState>>,
+    #[account(mut, seeds = [player_seed(victim.is_dummy), victim.wallet.as_ref()], bump, constraint = victim.arena == arena.key() @ CombatError::WrongArena)]
+    pub victim: Box<Account<'info, PlayerState>>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Box<Account<'info, Arena>>,
+    /// CHECK: Live talent rebalance PDA, read by `load_talent_tables`; may not exist yet
+    #[account(seeds = [TALENT_TABLES_SEED, arena.key().as_ref()], bump)]
+    pub talent_tables: UncheckedAccount<'info>,
+    /// Required when either side is in a duel
+    #[account(mut)]
+    pub duel: Option<Box<Account<'info, Duel>>>,
+}
+
+#[derive(Accounts)]
+pub struct SimulateAttack<'info> {
+    #[account(constraint = attacker.arena == arena.key() @ CombatError::WrongArena)]
+    pub attacker: Box<Account<'info, PlayerState>>,
+    #[account(constraint = victim.arena == arena.key() @ CombatError::WrongArena)]
+    pub victim: Box<Account<'info, PlayerState>>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Box<Account<'info, Arena>>,
+    /// CHECK: Talent tables PDA, read by `load_talent_tables`; may not exist yet
+    #[account(seeds = [TALENT_TABLES_SEED, arena.key().as_ref()], bump)]
+    pub talent_tables: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetArenaStats<'info> {
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct GetRespawnCountdown<'info> {
+    pub player_state: Account<'info, PlayerState>,
+}
+
+#[derive(Accounts)]
+pub struct GetPlayerSummary<'info> {
+    #[account(constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+    /// CHECK: Talent tables PDA, read by `load_talent_tables`; may not exist yet
+    #[account(seeds = [TALENT_TABLES_SEED, arena.key().as_ref()], bump)]
+    pub talent_tables: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PlayerRankKey<'info> {
+    pub player_state: Account<'info, PlayerState>,
+}
+
+#[derive(Accounts)]
+pub struct RespawnPlayer<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct UpgradeStat<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct ResetPlayer<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct AllocateTalent<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct CanAllocateTalent<'info> {
+    #[account(constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyTalentLoadout<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct DeallocateTalent<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct ResetTalents<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct SetArenaConfig<'info> {
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetArenaActive<'info> {
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ForceResetArena<'info> {
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(arena_id: u16)]
+pub struct MigrateArena<'info> {
+    /// CHECK: Old arena account that needs resizing; owner and discriminator checked in handler
+    #[account(mut, owner = crate::ID, seeds = [ARENA_SEED, arena_id_seed(arena_id).as_ref()], bump)]
+    pub arena: AccountInfo<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePlayer<'info> {
+    /// CHECK: Old player account that needs resizing
+    #[account(mut)]
+    pub player_state: AccountInfo<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct CommitState<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct CommitPlayer<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[commit]
+#[derive(Accounts)]
+pub struct EndSession<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct InitControlPoint<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 8 + 8,
+        seeds = [CONTROL_POINT_SEED, arena.key().as_ref()],
+        bump,
+    )]
+    pub control_point: Account<'info, ControlPoint>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[delegate]
+#[derive(Accounts)]
+#[instruction(arena_id: u16)]
+pub struct DelegateControlPointCtx<'info> {
+    pub payer: Signer<'info>,
+    /// CHECK: Arena the control point belongs to (may already be delegated); only its key feeds the control point seeds
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena_id).as_ref()], bump)]
+    pub arena: AccountInfo<'info>,
+    /// CHECK: Control point PDA to delegate
+    #[account(mut, del, seeds = [CONTROL_POINT_SEED, arena.key().as_ref()], bump)]
+    pub control_point: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CapturePoint<'info> {
+    #[account(constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [CONTROL_POINT_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub control_point: Account<'info, ControlPoint>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPointXp<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [CONTROL_POINT_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub control_point: Account<'info, ControlPoint>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+// ─── Events ──────────────────────────────────────────────────────────────────
+
+// Arena lifecycle. `authority` is the signer behind the transition (the payer
+// for delegation, which does not read the arena's data).
+
+#[event]
+pub struct ArenaInitializedEvent {
+    pub arena: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
 }
 
-// ─── Account Structs ─────────────────────────────────────────────────────────
+#[event]
+pub struct ArenaDelegatedEvent {
+    pub arena: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
 
-#[account]
-pub struct Arena {
+#[event]
+pub struct PlayerJoinedEvent {
+    pub wallet: Pubkey,
+    pub arena: Pubkey,
+    /// This call delegated the arena (false when it already was)
+    pub arena_newly_delegated: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ArenaPausedEvent {
+    pub arena: Pubkey,
     pub authority: Pubkey,
-    pub player_count: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ArenaResumedEvent {
+    pub arena: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ArenaResetEvent {
+    pub arena: Pubkey,
+    pub authority: Pubkey,
+    pub players_reset: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SessionEndedEvent {
+    pub arena: Pubkey,
+    pub authority: Pubkey,
+    /// Final count for the session that just ended
+    pub unique_killers: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MatchEndedEvent {
+    pub arena: Pubkey,
+    pub winner: Pubkey,
     pub total_kills: u64,
-    pub is_active: bool,
 }
 
-#[account]
-pub struct PlayerState {
-    pub wallet: Pubkey,
-    pub health: u16,
-    pub max_health: u16,
-    pub attack_power: u16,
-    pub xp: u64,
+#[event]
+pub struct PlayerWonEvent {
+    pub arena: Pubkey,
+    pub winner: Pubkey,
     pub kills: u64,
-    pub deaths: u64,
-    pub health_level: u8,
-    pub attack_level: u8,
-    pub is_alive: bool,
-    pub respawn_at: i64,
-    pub initialized: bool,
-    pub talent_iron_skin: u8,
-    pub talent_heavy_hitter: u8,
-    pub talent_regeneration: u8,
-    pub talent_lifesteal: u8,
-    pub talent_armor: u8,
-    pub talent_swift: u8,
-    pub talent_rapid_fire: u8,
-    pub talent_evasion: u8,
-    pub talent_quick_respawn: u8,
-    pub talent_momentum: u8,
-    pub talent_weakspot: u8,
-    pub talent_critical_strike: u8,
-    pub talent_focus_fire: u8,
-    pub talent_multi_shot: u8,
-    pub talent_dual_cannon: u8,
-    pub talent_deflect: u8,
-    pub talent_absorb: u8,
-    pub talent_last_stand: u8,
-    pub talent_cloak: u8,
-    pub talent_dash: u8,
-    pub talent_rampage: u8,
-    pub talent_homing: u8,
-    pub talent_ricochet: u8,
-    pub talent_deathbomb: u8,
-    pub talent_frenzy: u8,
-    pub manual_build: bool,
 }
 
-impl PlayerState {
-    pub fn get_talent(&self, id: u8) -> u8 {
-        match id {
-            0 => self.talent_iron_skin,
-            1 => self.talent_heavy_hitter,
-            2 => self.talent_regeneration,
-            3 => self.talent_lifesteal,
-            4 => self.talent_armor,
-            5 => self.talent_swift,
-            6 => self.talent_rapid_fire,
-            7 => self.talent_evasion,
-            8 => self.talent_quick_respawn,
-            9 => self.talent_momentum,
-            10 => self.talent_weakspot,
-            11 => self.talent_critical_strike,
-            12 => self.talent_focus_fire,
-            13 => self.talent_multi_shot,
-            14 => self.talent_dual_cannon,
-            15 => self.talent_deflect,
-            16 => self.talent_absorb,
-            17 => self.talent_last_stand,
-            18 => self.talent_cloak,
-            19 => self.talent_dash,
-            20 => self.talent_rampage,
-            21 => self.talent_homing,
-            22 => self.talent_ricochet,
-            23 => self.talent_deathbomb,
-            24 => self.talent_frenzy,
-            _ => 0,
-        }
-    }
+#[derive(Accounts)]
+pub struct CastUlt<'info> {
+    #[account(mut, constraint = caster.arena == arena.key() @ CombatError::WrongArena)]
+    pub caster: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
 
-    pub fn set_talent(&mut self, id: u8, val: u8) {
-        match id {
-            0 => self.talent_iron_skin = val,
-            1 => self.talent_heavy_hitter = val,
-            2 => self.talent_regeneration = val,
-            3 => self.talent_lifesteal = val,
-            4 => self.talent_armor = val,
-            5 => self.talent_swift = val,
-            6 => self.talent_rapid_fire = val,
-            7 => self.talent_evasion = val,
-            8 => self.talent_quick_respawn = val,
-            9 => self.talent_momentum = val,
-            10 => self.talent_weakspot = val,
-            11 => self.talent_critical_strike = val,
-            12 => self.talent_focus_fire = val,
-            13 => self.talent_multi_shot = val,
-            14 => self.talent_dual_cannon = val,
-            15 => self.talent_deflect = val,
-            16 => self.talent_absorb = val,
-            17 => self.talent_last_stand = val,
-            18 => self.talent_cloak = val,
-            19 => self.talent_dash = val,
-            20 => self.talent_rampage = val,
-            21 => self.talent_homing = val,
-            22 => self.talent_ricochet = val,
-            23 => self.talent_deathbomb = val,
-            24 => self.talent_frenzy = val,
-            _ => {}
-        }
-    }
+#[event]
+pub struct UltCastEvent {
+    pub caster: Pubkey,
+    pub targets: u8,
+    pub total_damage: u32,
+    pub ready_at: i64,
+}
 
-    pub fn total_talent_points_spent(&self) -> u16 {
-        (self.talent_iron_skin as u16)
-            + (self.talent_heavy_hitter as u16)
-            + (self.talent_regeneration as u16)
-            + (self.talent_lifesteal as u16)
-            + (self.talent_armor as u16)
-            + (self.talent_swift as u16)
-            + (self.talent_rapid_fire as u16)
-            + (self.talent_evasion as u16)
-            + (self.talent_quick_respawn as u16)
-            + (self.talent_momentum as u16)
-            + (self.talent_weakspot as u16)
-            + (self.talent_critical_strike as u16)
-            + (self.talent_focus_fire as u16)
-            + (self.talent_multi_shot as u16)
-            + (self.talent_dual_cannon as u16)
-            + (self.talent_deflect as u16)
-            + (self.talent_absorb as u16)
-            + (self.talent_last_stand as u16)
-            + (self.talent_cloak as u16)
-            + (self.talent_dash as u16)
-            + (self.talent_rampage as u16)
-            + (self.talent_homing as u16)
-            + (self.talent_ricochet as u16)
-            + (self.talent_deathbomb as u16)
-            + (self.talent_frenzy as u16)
-    }
+#[commit]
+#[derive(Accounts)]
+pub struct Forfeit<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, payer.key().as_ref()], bump, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
 }
 
-// ─── Instruction Contexts ────────────────────────────────────────────────────
+#[event]
+pub struct ForfeitEvent {
+    pub wallet: Pubkey,
+    pub respawn_at: i64,
+    pub undelegated: bool,
+    pub xp_penalty: u64,
+}
+
+#[event]
+pub struct KillEvent {
+    pub attacker: Pubkey,
+    pub victim: Pubkey,
+    pub total_damage: u16,
+    pub kill_xp: u64,
+    pub overkill_xp: u64,
+    pub execute_xp: u64,
+    /// Kill XP withheld by anti-stomp, before the arena XP multiplier
+    pub anti_stomp_xp: u64,
+    pub kill_heal: u16,
+    pub top_damager: Pubkey,
+    pub top_damage: u16,
+}
 
 #[derive(Accounts)]
-pub struct InitArena<'info> {
+pub struct InitMatchQueue<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 4 + 8 + 1,
-        seeds = [ARENA_SEED],
+        space = 8 + MatchQueue::INIT_SPACE,
+        seeds = [MATCH_QUEUE_SEED, arena.key().as_ref()],
         bump,
     )]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
     pub arena: Account<'info, Arena>,
     #[account(mut)]
     pub authority: Signer<'info>,
@@ -814,115 +4763,351 @@ pub struct InitArena<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(wallet: Pubkey)]
-pub struct RegisterPlayer<'info> {
+pub struct JoinQueue<'info> {
+    pub wallet: Signer<'info>,
+    #[account(seeds = [PLAYER_SEED, wallet.key().as_ref()], bump, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [MATCH_QUEUE_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct PopMatch<'info> {
+    #[account(mut, seeds = [MATCH_QUEUE_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[event]
+pub struct MatchFoundEvent {
+    pub player_a: Pubkey,
+    pub player_b: Pubkey,
+    pub level_gap: u8,
+}
+
+#[event]
+pub struct RevengeEvent {
+    pub avenger: Pubkey,
+    pub victim: Pubkey,
+}
+
+#[event]
+pub struct EliminationWinnerEvent {
+    pub arena: Pubkey,
+    pub winner: Pubkey,
+    pub kills: u64,
+}
+
+#[derive(Accounts)]
+pub struct GrantXp<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[event]
+pub struct GrantXpEvent {
+    pub wallet: Pubkey,
+    pub amount: u64,
+    pub new_xp: u64,
+    pub new_level: u8,
+}
+
+#[derive(Accounts)]
+pub struct Taunt<'info> {
+    pub wallet: Signer<'info>,
+    #[account(seeds = [PLAYER_SEED, wallet.key().as_ref()], bump, constraint = taunter_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub taunter_state: Account<'info, PlayerState>,
+    #[account(mut, constraint = target.arena == arena.key() @ CombatError::WrongArena)]
+    pub target: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[event]
+pub struct TauntEvent {
+    pub taunter: Pubkey,
+    pub target: Pubkey,
+    pub until: i64,
+}
+
+#[derive(Accounts)]
+pub struct ReconcileStats<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct UseDash<'info> {
+    pub wallet: Signer<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, wallet.key().as_ref()], bump, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[event]
+pub struct DashUsedEvent {
+    pub wallet: Pubkey,
+    pub ready_at: i64,
+}
+
+#[derive(Accounts)]
+pub struct TickRegen<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[derive(Accounts)]
+pub struct UseHeal<'info> {
+    pub wallet: Signer<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, wallet.key().as_ref()], bump, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[event]
+pub struct HealUsedEvent {
+    pub wallet: Pubkey,
+    pub healed: u16,
+    pub ready_at: i64,
+}
+
+#[event]
+pub struct TalentRefundEvent {
+    pub wallet: Pubkey,
+    /// One entry per refunded rank, in the order they were removed
+    pub slots: Vec<u8>,
+}
+
+#[derive(Accounts)]
+pub struct InitAllowlist<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 2 + 2 + 2 + 8 + 8 + 8 + 1 + 1 + 1 + 8 + 1 + 25 + 1,
-        seeds = [PLAYER_SEED, wallet.as_ref()],
+        space = 8 + Allowlist::INIT_SPACE,
+        seeds = [ALLOWLIST_SEED, arena.key().as_ref()],
         bump,
     )]
-    pub player_state: Account<'info, PlayerState>,
+    pub allowlist: Account<'info, Allowlist>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-#[delegate]
 #[derive(Accounts)]
-pub struct DelegateArenaCtx<'info> {
-    pub payer: Signer<'info>,
-    /// CHECK: Arena PDA to delegate
-    #[account(mut, del, seeds = [ARENA_SEED], bump)]
-    pub arena: AccountInfo<'info>,
+pub struct UpdateAllowlist<'info> {
+    #[account(mut, seeds = [ALLOWLIST_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub allowlist: Account<'info, Allowlist>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
 }
 
-#[delegate]
 #[derive(Accounts)]
-#[instruction(wallet: Pubkey)]
-pub struct DelegatePlayerCtx<'info> {
-    pub payer: Signer<'info>,
-    /// CHECK: Player PDA to delegate
-    #[account(mut, del, seeds = [PLAYER_SEED, wallet.as_ref()], bump)]
-    pub player_state: AccountInfo<'info>,
+pub struct InitTalentTables<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TalentTables::INIT_SPACE,
+        seeds = [TALENT_TABLES_SEED, arena.key().as_ref()],
+        bump,
+    )]
+    pub talent_tables: Account<'info, TalentTables>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ProcessAttack<'info> {
-    #[account(mut)]
-    pub attacker: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub victim: Account<'info, PlayerState>,
-    #[account(mut, seeds = [ARENA_SEED], bump)]
+pub struct SetTalentTables<'info> {
+    #[account(mut, seeds = [TALENT_TABLES_SEED, arena.key().as_ref()], bump, has_one = arena)]
+    pub talent_tables: Account<'info, TalentTables>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
     pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RespawnPlayer<'info> {
-    #[account(mut)]
+pub struct SanitizePlayer<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
     pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct UpgradeStat<'info> {
-    #[account(mut)]
+pub struct RefreshComeback<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
     pub player_state: Account<'info, PlayerState>,
+    /// The arena's top player by level, as the server tracks it
+    #[account(constraint = top_player.arena == arena.key() @ CombatError::WrongArena)]
+    pub top_player: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ResetPlayer<'info> {
-    #[account(mut)]
+pub struct SetInZone<'info> {
+    #[account(mut, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
     pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct AllocateTalent<'info> {
-    #[account(mut)]
+pub struct ZoneTick<'info> {
+    #[account(mut, seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump, has_one = authority @ CombatError::Unauthorized)]
+    pub arena: Account<'info, Arena>,
+    pub authority: Signer<'info>,
+}
+
+#[event]
+pub struct ZoneDamageEvent {
+    pub wallet: Pubkey,
+    pub damage: u16,
+    pub killed: bool,
+}
+
+#[event]
+pub struct LastStandEvent {
+    pub wallet: Pubkey,
+    pub attacker: Pubkey,
+    pub invulnerable_until: i64,
+}
+
+/// A player died or forfeited; the server can re-insert them at `respawn_at`.
+#[event]
+pub struct RespawnReadyEvent {
+    pub wallet: Pubkey,
+    pub respawn_at: i64,
+}
+
+#[derive(Accounts)]
+pub struct FullHeal<'info> {
+    pub wallet: Signer<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, wallet.key().as_ref()], bump, constraint = player_state.arena == arena.key() @ CombatError::WrongArena)]
     pub player_state: Account<'info, PlayerState>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
+}
+
+#[event]
+pub struct FullHealEvent {
+    pub wallet: Pubkey,
+    pub healed: u16,
+    pub health: u16,
+}
+
+#[event]
+pub struct PhaseChangedEvent {
+    pub arena: Pubkey,
+    pub from: u8,
+    pub to: u8,
+    pub timestamp: i64,
 }
 
 #[derive(Accounts)]
-pub struct ResetTalents<'info> {
-    #[account(mut)]
+pub struct SetSkin<'info> {
+    pub wallet: Signer<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, wallet.key().as_ref()], bump)]
     pub player_state: Account<'info, PlayerState>,
 }
 
+#[event]
+pub struct SkinChangedEvent {
+    pub wallet: Pubkey,
+    pub previous: u16,
+    pub skin_id: u16,
+}
+
+#[event]
+pub struct BountyClaimedEvent {
+    pub arena: Pubkey,
+    pub killer: Pubkey,
+    pub target: Pubkey,
+    pub bounty_xp: u64,
+}
+
 #[derive(Accounts)]
-pub struct MigratePlayer<'info> {
-    /// CHECK: Old player account that needs resizing
-    #[account(mut)]
-    pub player_state: AccountInfo<'info>,
+pub struct ChallengeDuel<'info> {
+    #[account(
+        init,
+        payer = challenger,
+        space = 8 + Duel::INIT_SPACE,
+        seeds = [DUEL_SEED, challenger.key().as_ref()],
+        bump,
+    )]
+    pub duel: Account<'info, Duel>,
+    #[account(seeds = [ARENA_SEED, arena_id_seed(arena.arena_id).as_ref()], bump)]
+    pub arena: Account<'info, Arena>,
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub challenger: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-#[commit]
 #[derive(Accounts)]
-pub struct CommitState<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(mut, seeds = [ARENA_SEED], bump)]
-    pub arena: Account<'info, Arena>,
+pub struct AcceptDuel<'info> {
+    #[account(mut, seeds = [DUEL_SEED, duel.challenger.as_ref()], bump, has_one = opponent)]
+    pub duel: Account<'info, Duel>,
+    #[account(mut, seeds = [PLAYER_SEED, duel.challenger.as_ref()], bump)]
+    pub challenger_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [PLAYER_SEED, opponent.key().as_ref()], bump)]
+    pub opponent_state: Account<'info, PlayerState>,
+    pub opponent: Signer<'info>,
 }
 
-#[commit]
 #[derive(Accounts)]
-pub struct CommitPlayer<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+pub struct CloseDuel<'info> {
+    #[account(
+        mut,
+        close = challenger,
+        seeds = [DUEL_SEED, challenger.key().as_ref()],
+        bump,
+        has_one = challenger,
+        constraint = closer.key() == duel.challenger || closer.key() == duel.opponent @ CombatError::Unauthorized
+    )]
+    pub duel: Account<'info, Duel>,
+    #[account(mut, seeds = [PLAYER_SEED, challenger.key().as_ref()], bump)]
+    pub challenger_state: Account<'info, PlayerState>,
+    #[account(mut, seeds = [PLAYER_SEED, duel.opponent.as_ref()], bump)]
+    pub opponent_state: Account<'info, PlayerState>,
+    /// CHECK: The duel's challenger (has_one), refunded the rent
     #[account(mut)]
-    pub player_state: Account<'info, PlayerState>,
+    pub challenger: UncheckedAccount<'info>,
+    pub closer: Signer<'info>,
 }
 
-#[commit]
-#[derive(Accounts)]
-pub struct EndSession<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(mut, seeds = [ARENA_SEED], bump)]
-    pub arena: Account<'info, Arena>,
+#[event]
+pub struct DuelStartedEvent {
+    pub duel: Pubkey,
+    pub challenger: Pubkey,
+    pub opponent: Pubkey,
+}
+
+#[event]
+pub struct DuelEndedEvent {
+    pub duel: Pubkey,
+    pub winner: Pubkey,
+    pub loser: Pubkey,
 }
 
 // ─── Errors ──────────────────────────────────────────────────────────────────
@@ -955,10 +5140,1224 @@ pub enum CombatError {
     TalentMaxed,
     #[msg("Prerequisite talent not met")]
     PrerequisiteNotMet,
-    #[msg("Maximum capstone talents (2) already chosen")]
+    #[msg("Maximum capstone talents for this arena (max_capstones) already chosen")]
     MaxCapstones,
     #[msg("Invalid hit count")]
     InvalidHitCount,
     #[msg("Invalid migration: account is not a valid old-format PlayerState")]
     InvalidMigration,
+    #[msg("Spent talent points exceed the budget for this XP; reset talents first")]
+    BuildExceedsBudget,
+    #[msg("Signer is not the arena authority")]
+    Unauthorized,
+    #[msg("Training dummies cannot attack")]
+    DummyCannotAttack,
+    #[msg("Player already holds the control point")]
+    AlreadyHoldingPoint,
+    #[msg("Player does not hold the control point")]
+    NotPointHolder,
+    #[msg("Invalid arena config value")]
+    InvalidConfig,
+    #[msg("Commit account is not a PlayerState, Arena, ControlPoint or Duel owned by this program")]
+    InvalidCommitAccount,
+    #[msg("Validator clock is outside the plausible window for this timer")]
+    ClockAnomaly,
+    #[msg("Batch must be non-empty, within the size limit, and match the accounts passed")]
+    InvalidBatchSize,
+    #[msg("Account is not the player PDA for this wallet")]
+    InvalidPlayerAccount,
+    #[msg("Ult requires at least one rank in the Shockwave capstone")]
+    UltUnavailable,
+    #[msg("Ability is still on cooldown")]
+    AbilityCooldown,
+    #[msg("Talent has no ranks to remove")]
+    TalentNotAllocated,
+    #[msg("Player has already forfeited this match")]
+    AlreadyForfeited,
+    #[msg("Player is already in the match queue")]
+    AlreadyQueued,
+    #[msg("Match queue is full")]
+    QueueFull,
+    #[msg("Fewer than two players are waiting")]
+    NoMatchAvailable,
+    #[msg("Commit chunk out of order or chunk count mismatch")]
+    InvalidCommitChunk,
+    #[msg("Respawning is disabled in elimination mode")]
+    EliminationMode,
+    #[msg("XP grant must be between 1 and MAX_GRANT_XP")]
+    InvalidGrantAmount,
+    #[msg("Taunts are disabled in this arena")]
+    TauntDisabled,
+    #[msg("Invalid taunt target or duration")]
+    InvalidTaunt,
+    #[msg("Dash requires at least one rank in the Dash talent")]
+    DashUnavailable,
+    #[msg("Heal is disabled in this arena")]
+    HealUnavailable,
+    #[msg("Heal can only be used in combat")]
+    NotInCombat,
+    #[msg("Player level is too low for capstone talents")]
+    CapstoneLevelLocked,
+    #[msg("Wallet is not on this arena's allowlist")]
+    NotAllowlisted,
+    #[msg("Allowlist is full")]
+    AllowlistFull,
+    #[msg("Account is not a PlayerState")]
+    MigrationBadDiscriminator,
+    #[msg("Account is larger than the current PlayerState layout")]
+    MigrationAccountTooLarge,
+    #[msg("Only available in practice arenas")]
+    PracticeOnly,
+    #[msg("Arena is not in the required phase")]
+    WrongPhase,
+    #[msg("Stored talent rank exceeds the talent's max; run sanitize_player")]
+    TalentRankCorrupt,
+    #[msg("Distance bucket outside the falloff table")]
+    InvalidDistanceBucket,
+    #[msg("Talents are disabled in this arena")]
+    TalentsDisabled,
+    #[msg("Dueling players can only attack each other, and cannot reset or leave a running duel")]
+    DuelRestricted,
+    #[msg("Duel cannot be started")]
+    DuelUnavailable,
+    #[msg("Player is registered in a different arena")]
+    WrongArena,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_player() -> PlayerState {
+        let mut p = PlayerState::default();
+        init_base_player(&mut p, Pubkey::new_unique());
+        p
+    }
+
+    /// Per-hit damage, `crit` being the hit's own roll.
+    fn compute_hit_damage(
+        attacker: &PlayerState,
+        victim: &PlayerState,
+        config: &ArenaConfig,
+        tables: &TalentTableValues,
+        now: i64,
+        crit: bool,
+    ) -> u16 {
+        let eff_max = (effective_max_health(attacker, config), effective_max_health(victim, config));
+        hit_damage_breakdown(attacker, victim, config, tables, eff_max, now, crit).final_damage
+    }
+
+    /// Volley total when every hit deals `damage_per_hit`.
+    fn calc_total_damage(damage_per_hit: u16, hit_count: u16, config: &ArenaConfig) -> u16 {
+        let b = DamageBreakdown { final_damage: damage_per_hit, ..Default::default() };
+        calc_volley_damage(&b, hit_count, config)
+    }
+
+    fn test_arena() -> Arena {
+        Arena {
+            authority: Pubkey::new_unique(),
+            player_count: 4,
+            total_kills: 0,
+            is_active: true,
+            config: ArenaConfig::default(),
+            winner: None,
+            arena_id: 0,
+            commit_chunk_next: 0,
+            commit_chunk_total: 0,
+            total_damage_dealt: 0,
+            alive_count: 4,
+            unique_killers: 0,
+            zone: ZoneState::default(),
+            last_commit_at: 0,
+            dirty_since_commit: 0,
+            phase: PHASE_LIVE,
+            bounty_target: Pubkey::default(),
+            bounty_xp: 0,
+            session_id: 1,
+            event_seq: 0,
+        }
+    }
+
+    #[test]
+    fn arena_binding_offset() {
+        let mut p = new_player();
+        p.arena = Pubkey::new_unique();
+        let mut data = Vec::new();
+        p.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), PLAYER_STATE_SPACE);
+        assert_eq!(&data[PLAYER_ARENA_OFFSET..PLAYER_ARENA_OFFSET + 32], p.arena.as_ref());
+    }
+
+    #[test]
+    fn reset_keeps_arena() {
+        let mut p = new_player();
+        let arena = Pubkey::new_unique();
+        p.arena = arena;
+        p.xp = 500;
+        reset_to_base(&mut p);
+        assert_eq!((p.arena, p.xp), (arena, 0));
+    }
+
+    #[test]
+    fn build_budget_guard() {
+        let mut p = new_player();
+        // Level 9 grants 5 points; spend all of them
+        p.xp = level_floor_xp(9);
+        for slot in 0..5u8 {
+            p.set_talent(slot, 1);
+        }
+        assert!(require_build_affordable(&p, p.xp).is_ok());
+        // One XP short of level 9 is level 8, a 4-point budget
+        assert!(require_build_affordable(&p, p.xp - 1).is_err());
+        assert!(matches!(
+            check_talent_allocation(&p, 5, &ArenaConfig::default()),
+            Err(CombatError::NoTalentPoints)
+        ));
+        p.xp = level_floor_xp(11);
+        assert!(check_talent_allocation(&p, 5, &ArenaConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn dummy_pda_is_distinct() {
+        let wallet = Pubkey::new_unique();
+        let (player, _) = Pubkey::find_program_address(&[player_seed(false), wallet.as_ref()], &crate::ID);
+        let (dummy, _) = Pubkey::find_program_address(&[player_seed(true), wallet.as_ref()], &crate::ID);
+        assert_ne!(player, dummy);
+        assert_eq!(player_seed(false), PLAYER_SEED);
+    }
+
+    #[test]
+    fn max_hit_count_config() {
+        let mut cfg = ArenaConfig::default();
+        assert!(cfg.validate().is_ok());
+        cfg.max_hit_count = 0;
+        assert!(cfg.validate().is_err());
+        cfg.max_hit_count = 500;
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn global_damage_reduction() {
+        let (a, v) = (new_player(), new_player());
+        let tables = TalentTableValues::default();
+        let mut cfg = ArenaConfig::default();
+        let full = compute_hit_damage(&a, &v, &cfg, &tables, 0, false);
+        cfg.global_damage_reduction_bps = 5000;
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), full / 2);
+        // Full reduction still leaves the 1-damage floor
+        cfg.global_damage_reduction_bps = 10000;
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 1);
+    }
+
+    #[test]
+    fn zero_max_health_falls_back() {
+        let mut p = new_player();
+        p.max_health = 0;
+        assert_eq!(effective_max_health(&p, &ArenaConfig::default()), BASE_HEALTH as u32);
+    }
+
+    #[test]
+    fn sustain_order() {
+        let tables = TalentTableValues::default();
+        let mut attacker = new_player();
+        attacker.talent_lifesteal = 5;
+        attacker.health = 70;
+        let mut victim = new_player();
+        victim.talent_absorb = 5;
+        let run = |order: CombatOrder| {
+            let cfg = ArenaConfig { combat_order: order, ..ArenaConfig::default() };
+            let mut a = attacker.clone();
+            let eff_max = (effective_max_health(&a, &cfg), effective_max_health(&victim, &cfg));
+            let (healed, countered) = apply_sustain(&mut a, &victim, 200, 10, &cfg, &tables, eff_max, 0, 0);
+            (a.health, healed, countered)
+        };
+        let (heal_first, healed_a, countered_a) = run(CombatOrder::LifestealBeforeCounter);
+        let (counter_first, healed_b, countered_b) = run(CombatOrder::CounterBeforeLifesteal);
+        assert_eq!(countered_a, countered_b);
+        assert!(countered_a > 0);
+        // Healing first is cut off by the ceiling; after the counter it has room
+        assert_eq!(healed_a, 10);
+        assert!(healed_b > healed_a);
+        assert!(counter_first > heal_first);
+    }
+
+    #[test]
+    fn respawn_hp_fraction() {
+        let mut p = new_player();
+        let mut cfg = ArenaConfig::default();
+        let eff_max = effective_max_health(&p, &cfg) as u16;
+        assert_eq!(respawn_health(&mut p, &cfg), eff_max);
+        cfg.respawn_hp_bps = 5000;
+        assert_eq!(respawn_health(&mut p, &cfg), eff_max / 2);
+        cfg.respawn_hp_bps = 1;
+        assert_eq!(respawn_health(&mut p, &cfg), 1);
+    }
+
+    #[test]
+    fn kill_xp_curves() {
+        let p = new_player();
+        let xp = |curve: KillXpCurve, level: u64| {
+            calc_kill_xp(&p, level, &ArenaConfig { kill_xp_curve: curve, ..ArenaConfig::default() })
+        };
+        for curve in [KillXpCurve::Linear, KillXpCurve::Sqrt, KillXpCurve::Capped] {
+            assert_eq!(xp(curve, 1), XP_PER_KILL_BASE);
+        }
+        let pivot = 1 + KILL_XP_SQRT_PIVOT;
+        assert_eq!(xp(KillXpCurve::Sqrt, pivot), xp(KillXpCurve::Linear, pivot));
+        assert!(xp(KillXpCurve::Sqrt, 45) < xp(KillXpCurve::Linear, 45));
+        assert!(xp(KillXpCurve::Sqrt, 10) > xp(KillXpCurve::Linear, 10));
+        assert_eq!(xp(KillXpCurve::Capped, 45), xp(KillXpCurve::Capped, KILL_XP_CAP_LEVEL));
+        assert_eq!(xp(KillXpCurve::Capped, KILL_XP_CAP_LEVEL), xp(KillXpCurve::Linear, KILL_XP_CAP_LEVEL));
+    }
+
+    #[test]
+    fn difficulty_scales_volley() {
+        let mut cfg = ArenaConfig::default();
+        assert_eq!(calc_total_damage(50, 4, &cfg), 200);
+        cfg.difficulty_bps = 15000;
+        assert_eq!(calc_total_damage(50, 4, &cfg), 300);
+        cfg.difficulty_bps = 5000;
+        assert_eq!(calc_total_damage(50, 4, &cfg), 100);
+        // Saturates at u16 instead of wrapping
+        cfg.difficulty_bps = 20000;
+        assert_eq!(calc_total_damage(u16::MAX, 4, &cfg), u16::MAX);
+    }
+
+    #[test]
+    fn armor_pen_vs_max_armor() {
+        let tables = TalentTableValues::default();
+        let mut a = new_player();
+        a.attack_power = 400;
+        let mut v = new_player();
+        v.talent_iron_skin = 5;
+        let mut cfg = ArenaConfig::default();
+        // Off by default: max Armor keeps its full 24%
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 304);
+        cfg.armor_pen_bps = 300;
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 316);
+        // Penetration past the victim's Armor floors it at zero
+        cfg.armor_pen_bps = 5000;
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 400);
+        cfg.armor_pen_bps = 10001;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn damage_models() {
+        let tables = TalentTableValues::default();
+        let mut a = new_player();
+        a.attack_power = 100;
+        a.talent_swift = 5;
+        a.talent_homing = 5;
+        let mut v = new_player();
+        v.health = 40;
+        let hit = |a: &PlayerState, model: DamageModel| {
+            let cfg = ArenaConfig { damage_model: model, ..ArenaConfig::default() };
+            compute_hit_damage(a, &v, &cfg, &tables, 0, false)
+        };
+        // +24% Heavy Hitter and +48% Execute: compounded vs summed
+        assert_eq!(hit(&a, DamageModel::Multiplicative), 183);
+        assert_eq!(hit(&a, DamageModel::Additive), 172);
+        // A single bonus is the same either way
+        a.talent_homing = 0;
+        assert_eq!(hit(&a, DamageModel::Multiplicative), hit(&a, DamageModel::Additive));
+    }
+
+    #[test]
+    fn execute_threshold() {
+        let mut v = new_player();
+        let mut cfg = ArenaConfig::default();
+        v.health = 50;
+        assert!(in_execute_range(&v, 100, &cfg));
+        v.health = 51;
+        assert!(!in_execute_range(&v, 100, &cfg));
+        cfg.execute_threshold_bps = 3000;
+        assert!(!in_execute_range(&v, 100, &cfg));
+        v.health = 30;
+        assert!(in_execute_range(&v, 100, &cfg));
+    }
+
+    #[test]
+    fn capstone_limit() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(MAX_LEVEL);
+        for slot in 0..14u8 {
+            p.set_talent(slot, 1);
+        }
+        assert_eq!(capstone_count(&p), 2);
+        let caps = |max_capstones: u8| ArenaConfig { max_capstones, ..ArenaConfig::default() };
+        assert!(matches!(check_talent_allocation(&p, 14, &caps(2)), Err(CombatError::MaxCapstones)));
+        assert!(check_talent_allocation(&p, 14, &caps(3)).is_ok());
+        // More ranks in a capstone already held don't count against the limit
+        assert!(check_talent_allocation(&p, 4, &caps(2)).is_ok());
+    }
+
+    #[test]
+    fn xp_table_matches_cost_curve() {
+        // Rebuild the thresholds from the per-level cost curve the table replaced
+        let mut total: u64 = 0;
+        let mut penalty_bp: u64 = 10000;
+        for lvl in 1..(MAX_LEVEL as u64) {
+            if lvl > 50 {
+                penalty_bp = penalty_bp * 106 / 100;
+            }
+            total += (2 * lvl - 1) * LEVEL_SCALE * penalty_bp / 10000;
+            let next = lvl as u8 + 1;
+            assert_eq!(level_floor_xp(next), total, "level {}", next);
+            assert_eq!(calc_level(total), next);
+            assert_eq!(calc_level(total - 1), next - 1);
+        }
+        assert_eq!(calc_level(0), 1);
+        assert_eq!(calc_level(u64::MAX), MAX_LEVEL);
+    }
+
+    #[test]
+    fn loadout_rules() {
+        let caps = |max_capstones: u8| ArenaConfig { max_capstones, ..ArenaConfig::default() };
+        let mut r = [0u8; 25];
+        assert!(validate_loadout(&r, 1, &caps(2)).is_ok());
+        // Over max rank
+        r[0] = 6;
+        assert!(validate_loadout(&r, MAX_LEVEL, &caps(2)).is_err());
+        // Gap in the prerequisite chain
+        r = [0; 25];
+        r[0] = 1;
+        r[2] = 1;
+        r[3] = 1;
+        assert!(validate_loadout(&r, MAX_LEVEL, &caps(2)).is_err());
+        r[1] = 1;
+        assert!(validate_loadout(&r, MAX_LEVEL, &caps(2)).is_ok());
+        // Every talent at rank 1: five capstones, 25 points
+        let all = [1u8; 25];
+        assert!(validate_loadout(&all, MAX_LEVEL, &caps(5)).is_ok());
+        assert!(validate_loadout(&all, MAX_LEVEL, &caps(4)).is_err());
+        assert!(validate_loadout(&all, 10, &caps(5)).is_err());
+    }
+
+    #[test]
+    fn berserker_ramp() {
+        let tables = TalentTableValues::default();
+        let v = new_player();
+        let mut a = new_player();
+        a.attack_power = 100;
+        a.talent_frenzy = 3;
+        let hit = |a: &PlayerState, smooth: bool| {
+            let cfg = ArenaConfig { berserker_smooth: smooth, ..ArenaConfig::default() };
+            compute_hit_damage(a, &v, &cfg, &tables, 0, false)
+        };
+        // Above 33% HP: no bonus either way
+        a.health = 34;
+        assert_eq!((hit(&a, false), hit(&a, true)), (100, 100));
+        // At the threshold: flat gives the full +30%, smooth starts at zero
+        a.health = 33;
+        assert_eq!((hit(&a, false), hit(&a, true)), (130, 100));
+        // A third of the way down: smooth has ramped to two thirds of the bonus
+        a.health = 11;
+        assert_eq!((hit(&a, false), hit(&a, true)), (130, 120));
+    }
+
+    #[test]
+    fn streak_grace() {
+        let mut arena = test_arena();
+        arena.config.streak_grace = true;
+        let mut v = new_player();
+        v.current_streak = 6;
+        record_death(&mut v, &mut arena, Pubkey::new_unique(), 1000);
+        assert_eq!(v.current_streak, 3);
+        // Still on cooldown: the next death clears the streak
+        v.current_streak = 6;
+        record_death(&mut v, &mut arena, Pubkey::new_unique(), 1000 + STREAK_GRACE_COOLDOWN_SECS - 1);
+        assert_eq!(v.current_streak, 0);
+        v.current_streak = 6;
+        record_death(&mut v, &mut arena, Pubkey::new_unique(), 1000 + STREAK_GRACE_COOLDOWN_SECS);
+        assert_eq!(v.current_streak, 3);
+        arena.config.streak_grace = false;
+        record_death(&mut v, &mut arena, Pubkey::new_unique(), 1_000_000);
+        assert_eq!(v.current_streak, 0);
+    }
+
+    #[test]
+    fn auto_level_toggle() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(12);
+        let mut cfg = ArenaConfig { auto_level: false, ..ArenaConfig::default() };
+        auto_level(&mut p, &cfg);
+        assert_eq!((p.health_level, p.attack_level), (1, 1));
+        cfg.auto_level = true;
+        auto_level(&mut p, &cfg);
+        assert_eq!((p.health_level, p.attack_level), (12, 12));
+    }
+
+    #[test]
+    fn manual_level_point_xp() {
+        let mut cfg = ArenaConfig { auto_level: false, ..ArenaConfig::default() };
+        let mut p = new_player();
+        p.xp = level_floor_xp(12) - 1;
+        let mut point = ControlPoint { arena: Pubkey::default(), owner: p.wallet, held_since: 1_000, last_claim_at: 1_000 };
+        assert_eq!(claim_point(&mut p, &mut point, 1_010, &cfg), (10 * POINT_XP_PER_SEC, 10));
+        assert_eq!(calc_level(p.xp), 12);
+        assert_eq!((p.health_level, p.attack_level), (1, 1));
+        // The next claim in an auto-level arena catches the levels up
+        cfg.auto_level = true;
+        claim_point(&mut p, &mut point, 1_020, &cfg);
+        assert_eq!((p.health_level, p.attack_level), (12, 12));
+    }
+
+    #[test]
+    fn auto_level_sync_gate() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(12);
+        let mut cfg = ArenaConfig::default();
+        auto_level(&mut p, &cfg);
+        assert_eq!(p.health_level, 12);
+        assert_eq!((p.max_health, p.attack_power), (BASE_HEALTH, BASE_ATTACK));
+        cfg.auto_level_sync = true;
+        auto_level(&mut p, &cfg);
+        assert!(p.max_health > BASE_HEALTH && p.attack_power > BASE_ATTACK);
+        // A living player gains the added max HP
+        assert_eq!(p.health, p.max_health);
+    }
+
+    #[test]
+    fn attack_pdas_follow_wallet() {
+        // process_attack derives each side's address from the wallet stored in
+        // the account, which has to match the address the server derives
+        let wallet = Pubkey::new_unique();
+        let (server_pda, _) = Pubkey::find_program_address(&[b"player_v2", wallet.as_ref()], &crate::ID);
+        let (attack_pda, _) = Pubkey::find_program_address(&[player_seed(false), wallet.as_ref()], &crate::ID);
+        assert_eq!(attack_pda, server_pda);
+        // An account claiming someone else's wallet sits at a different address
+        let other = Pubkey::new_unique();
+        let (spoof, _) = Pubkey::find_program_address(&[player_seed(false), other.as_ref()], &crate::ID);
+        assert_ne!(spoof, server_pda);
+    }
+
+    #[test]
+    fn damage_scale_config() {
+        let tables = TalentTableValues::default();
+        let mut a = new_player();
+        a.attack_power = 137;
+        a.talent_swift = 3;
+        let v = new_player();
+        let mut cfg = ArenaConfig::default();
+        let coarse = compute_hit_damage(&a, &v, &cfg, &tables, 0, false);
+        cfg.damage_scale = 10 * DAMAGE_SCALE as u16;
+        assert!(cfg.validate().is_ok());
+        // Finer intermediate stages, same HP units at the end
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), coarse);
+        cfg.damage_scale = DAMAGE_SCALE as u16 + 50;
+        assert!(cfg.validate().is_err());
+        cfg.damage_scale = 11 * DAMAGE_SCALE as u16;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn attack_power_per_level() {
+        let tables = TalentTableValues::default();
+        let v = new_player();
+        let mut a = new_player();
+        a.attack_power = 100;
+        a.attack_level = 11;
+        let mut cfg = ArenaConfig { attack_power_per_level: 0, ..ArenaConfig::default() };
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 100);
+        cfg.attack_power_per_level = 5;
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &tables, 0, false), 150);
+        cfg.attack_power_per_level = 51;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn unique_killers_per_session() {
+        let mut arena = test_arena();
+        let mut a = new_player();
+        for _ in 0..2 {
+            let mut v = new_player();
+            apply_death(&mut v, &mut a, &mut arena, 100);
+        }
+        assert_eq!(arena.unique_killers, 1);
+        // A reset mid-session doesn't make the same player a new killer
+        reset_to_base(&mut a);
+        apply_death(&mut new_player(), &mut a, &mut arena, 100);
+        assert_eq!(arena.unique_killers, 1);
+        next_session(&mut arena);
+        assert_eq!(arena.unique_killers, 0);
+        apply_death(&mut new_player(), &mut a, &mut arena, 100);
+        assert_eq!(arena.unique_killers, 1);
+        assert_eq!(a.kill_session, arena.session_id);
+    }
+
+    #[test]
+    fn xp_multiplier() {
+        let mut cfg = ArenaConfig::default();
+        assert_eq!(scale_xp(40, &cfg), 40);
+        cfg.xp_multiplier_bps = 20000;
+        assert_eq!(scale_xp(40, &cfg), 80);
+        cfg.xp_multiplier_bps = 15000;
+        assert_eq!(scale_xp(3, &cfg), 4);
+        assert_eq!(scale_xp(u64::MAX, &cfg), u64::MAX);
+        // Control point XP is scaled too
+        let mut p = new_player();
+        let mut point = ControlPoint { arena: Pubkey::default(), owner: p.wallet, held_since: 0, last_claim_at: 1_000 };
+        assert_eq!(claim_point(&mut p, &mut point, 1_100, &cfg).0, 150 * POINT_XP_PER_SEC);
+        assert_eq!(p.xp, 150 * POINT_XP_PER_SEC);
+    }
+
+    #[test]
+    fn anti_farm_buffer() {
+        let mut a = new_player();
+        let (farmed, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let now = 10_000;
+        for expected in 0..ANTI_FARM_SLOTS {
+            assert_eq!(record_victim(&mut a, farmed, now), expected);
+        }
+        // The buffer is full of one victim; a new one pushes the oldest out
+        assert_eq!(record_victim(&mut a, other, now), 0);
+        assert_eq!(record_victim(&mut a, farmed, now), ANTI_FARM_SLOTS - 1);
+        // A quiet spell past the window forgets everyone
+        assert_eq!(record_victim(&mut a, farmed, now + ANTI_FARM_WINDOW_SECS + 1), 0);
+    }
+
+    #[test]
+    fn crits_roll_per_hit() {
+        let hits = 100u16;
+        let (mut total, mut partial) = (0u64, 0);
+        for n in 0..2000u64 {
+            let crits = volley_crits(5, CritModel::Real, &CRIT_CHANCE_BPS, deterministic_seed(&[&n.to_le_bytes()]), hits);
+            assert!(crits <= hits);
+            if crits > 0 && crits < hits {
+                partial += 1;
+            }
+            total += crits as u64;
+        }
+        // Rank 5 crits 35% of hits on average; volleys land a share, not all or nothing
+        let mean_bps = total * 10000 / (2000 * hits as u64);
+        assert!((3300..=3700).contains(&mean_bps), "mean {}", mean_bps);
+        assert_eq!(partial, 2000);
+        assert_eq!(volley_crits(0, CritModel::Real, &CRIT_CHANCE_BPS, 7, hits), 0);
+        assert_eq!(volley_crits(5, CritModel::ExpectedValue, &CRIT_CHANCE_BPS, 7, hits), 0);
+        assert_eq!(volley_crits(5, CritModel::CappedBurst, &CRIT_CHANCE_BPS, 7, 0), 0);
+    }
+
+    #[test]
+    fn volley_mixes_crit_hits() {
+        let tables = TalentTableValues::default();
+        let mut a = new_player();
+        a.attack_power = 100;
+        a.talent_evasion = 5;
+        let v = new_player();
+        let cfg = ArenaConfig { crit_model: CritModel::Real, ..ArenaConfig::default() };
+        let eff_max = (effective_max_health(&a, &cfg), effective_max_health(&v, &cfg));
+        let seed = (0..).find(|&s| volley_crits(5, cfg.crit_model, &CRIT_CHANCE_BPS, s, 10) == 3).unwrap();
+        let b = volley_breakdown(&a, &v, &cfg, &tables, eff_max, 0, seed, 10);
+        assert_eq!((b.final_damage, b.crit_damage, b.crit_hits), (100, 300, 3));
+        assert_eq!(calc_volley_damage(&b, 10, &cfg), 7 * 100 + 3 * 300);
+    }
+
+    #[test]
+    fn capstone_level_gate() {
+        let cfg = ArenaConfig { capstone_min_level: 30, ..ArenaConfig::default() };
+        let mut r = [0u8; 25];
+        r[..5].copy_from_slice(&[1, 1, 1, 1, 1]);
+        assert!(validate_loadout(&r, 29, &cfg).is_err());
+        assert!(validate_loadout(&r, 30, &cfg).is_ok());
+        r[4] = 0;
+        assert!(validate_loadout(&r, 29, &cfg).is_ok());
+
+        let mut p = new_player();
+        p.xp = level_floor_xp(29);
+        for slot in 0..4u8 {
+            p.set_talent(slot, 1);
+        }
+        assert!(matches!(check_talent_allocation(&p, 4, &cfg), Err(CombatError::CapstoneLevelLocked)));
+        p.xp = level_floor_xp(30);
+        assert!(check_talent_allocation(&p, 4, &cfg).is_ok());
+    }
+
+    #[test]
+    fn life_damage_cap() {
+        let mut v = new_player();
+        // Budget 150: a fresh life takes up to 150 in one volley, 50 of it overkill
+        assert_eq!(clamp_life_damage(&v, 1000, 100), 150);
+        assert_eq!(clamp_life_damage(&v, 120, 100), 120);
+        // Healed up after soaking 140: 10 of budget left, but a kill still lands
+        v.damage_taken_this_life = 140;
+        assert_eq!(clamp_life_damage(&v, 1000, 100), 100);
+        v.health = 30;
+        assert_eq!(clamp_life_damage(&v, 5, 100), 5);
+        v.damage_taken_this_life = 500;
+        assert_eq!(clamp_life_damage(&v, 1000, 100), 30);
+    }
+
+    #[test]
+    fn registration_hp_scale() {
+        for (bps, want) in [(5000u16, 50u16), (10000, 100), (20000, 200)] {
+            let cfg = ArenaConfig { hp_scale_bps: bps, ..ArenaConfig::default() };
+            let mut p = new_player();
+            apply_start_profile(&mut p, &cfg);
+            assert_eq!(p.health, want);
+            assert_eq!(p.health as u32, effective_max_health(&p, &cfg));
+            // Iron Skin builds on the scaled pool
+            p.talent_heavy_hitter = 5;
+            apply_start_profile(&mut p, &cfg);
+            assert_eq!(p.health as u32, want as u32 * 13 / 10);
+        }
+    }
+
+    #[test]
+    fn refund_on_level_drop() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(60);
+        let budget = calc_talent_points(60);
+        // Fill the trees in order until the whole budget is spent
+        let mut left = budget;
+        'fill: for tree in TREE_SLOTS.iter() {
+            for &slot in tree.iter() {
+                let rank = max_rank_for_talent(slot).min(left as u8);
+                p.set_talent(slot, rank);
+                left -= rank as u16;
+                if left == 0 {
+                    break 'fill;
+                }
+            }
+        }
+        assert_eq!(p.total_talent_points_spent(), budget);
+        p.xp = level_floor_xp(20);
+        let refunded = refund_overflow(&mut p);
+        assert_eq!(p.total_talent_points_spent(), calc_talent_points(20));
+        assert_eq!(refunded.len() as u16, budget - calc_talent_points(20));
+        // Capstones go first, and what's left is still a legal build
+        assert!(CAPSTONE_SLOTS.contains(&refunded[0]));
+        let ranks: [u8; 25] = core::array::from_fn(|id| p.get_talent(id as u8));
+        let cfg = ArenaConfig { max_capstones: 5, ..ArenaConfig::default() };
+        assert!(validate_loadout(&ranks, 20, &cfg).is_ok());
+        assert!(refund_overflow(&mut p).is_empty());
+    }
+
+    #[test]
+    fn offensive_ceiling() {
+        let tables = TalentTableValues::default();
+        let v = new_player();
+        let mut a = new_player();
+        a.attack_power = 50;
+        a.talent_frenzy = 3;
+        a.talent_swift = 5;
+        a.talent_evasion = 5;
+        // Berserker active
+        a.health = 10;
+        let uncapped = compute_hit_damage(&a, &v, &ArenaConfig::default(), &tables, 0, false);
+        for model in [DamageModel::Multiplicative, DamageModel::Additive] {
+            let cfg = ArenaConfig { max_offensive_bps: 5000, damage_model: model, ..ArenaConfig::default() };
+            let capped = compute_hit_damage(&a, &v, &cfg, &tables, 0, false);
+            assert!(capped < uncapped);
+            assert_eq!(capped, 75);
+        }
+    }
+
+    #[test]
+    fn talent_table_overrides() {
+        let def = TalentTableValues::default();
+        assert!(def.validate().is_ok());
+        let mut a = new_player();
+        a.attack_power = 200;
+        let mut v = new_player();
+        v.talent_iron_skin = 5;
+        let cfg = ArenaConfig::default();
+        // Rank 5 Armor: 24% by default, 50% overridden, less any table penetration
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &def, 0, false), 152);
+        let armor = TalentTableValues { armor_bps: [500, 1000, 2000, 4000, 5000], ..def.clone() };
+        assert!(armor.validate().is_ok());
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &armor, 0, false), 100);
+        let pen = TalentTableValues { armor_pen_bps: 1000, ..armor.clone() };
+        assert_eq!(compute_hit_damage(&a, &v, &cfg, &pen, 0, false), 120);
+        // Rolled crits follow the table multiplier
+        let real = ArenaConfig { crit_model: CritModel::Real, ..ArenaConfig::default() };
+        a.talent_evasion = 5;
+        v.talent_iron_skin = 0;
+        let mult = TalentTableValues { crit_mult_bps: [15000; 5], ..def.clone() };
+        assert_eq!(compute_hit_damage(&a, &v, &real, &def, 0, true), 600);
+        assert_eq!(compute_hit_damage(&a, &v, &real, &mult, 0, true), 300);
+        // Chance: 0% never crits, 100% always does
+        let never = [0u32; 5];
+        let always = [10000u32; 5];
+        assert_eq!(volley_crits(5, CritModel::Real, &never, 7, 50), 0);
+        assert_eq!(volley_crits(5, CritModel::Real, &always, 7, 50), 50);
+
+        assert!(TalentTableValues { armor_bps: [500, 400, 2000, 4000, 5000], ..def.clone() }.validate().is_err());
+        assert!(TalentTableValues { armor_bps: [500, 1000, 2000, 4000, 9500], ..def.clone() }.validate().is_err());
+        assert!(TalentTableValues { crit_chance_bps: [0, 0, 0, 0, 10001], ..def.clone() }.validate().is_err());
+        assert!(TalentTableValues { crit_mult_bps: [9000, 20000, 20000, 20000, 20000], ..def.clone() }.validate().is_err());
+        assert!(TalentTableValues { armor_pen_bps: 9001, ..def }.validate().is_err());
+    }
+
+    #[test]
+    fn anti_stomp_gaps() {
+        assert_eq!(anti_stomp_bps(80, 1, &ArenaConfig::default()), 10000);
+        let on = ArenaConfig { anti_stomp: true, anti_stomp_gap: 10, ..ArenaConfig::default() };
+        // Upsets and gaps up to the threshold keep full XP
+        assert_eq!(anti_stomp_bps(1, 80, &on), 10000);
+        assert_eq!(anti_stomp_bps(20, 10, &on), 10000);
+        assert_eq!(anti_stomp_bps(21, 10, &on), 9000);
+        assert_eq!(anti_stomp_bps(25, 10, &on), 5000);
+        assert_eq!(anti_stomp_bps(30, 10, &on), 0);
+        assert_eq!(anti_stomp_bps(100, 1, &on), 0);
+    }
+
+    #[test]
+    fn migration_outcomes() {
+        let mut current = Vec::new();
+        new_player().try_serialize(&mut current).unwrap();
+        assert_eq!(current.len(), PLAYER_STATE_SPACE);
+        assert_eq!(player_schema_version(&current).unwrap(), Some(PLAYER_SCHEMA_VERSION));
+
+        let mut old = current[..FIRST_TAGGED_PLAYER_SPACE].to_vec();
+        *old.last_mut().unwrap() = 1;
+        assert_eq!(player_schema_version(&old).unwrap(), Some(1));
+        assert_eq!(player_schema_version(&current[..FIRST_TAGGED_PLAYER_SPACE - 1]).unwrap(), None);
+
+        let err = |data: &[u8]| player_schema_version(data).unwrap_err();
+        let mut large = current.clone();
+        large.push(0);
+        assert_eq!(err(&large), CombatError::MigrationAccountTooLarge.into());
+        let mut bad_disc = current.clone();
+        bad_disc[0] ^= 1;
+        assert_eq!(err(&bad_disc), CombatError::MigrationBadDiscriminator.into());
+        assert_eq!(err(&current[..4]), CombatError::MigrationBadDiscriminator.into());
+        for v in [0, PLAYER_SCHEMA_VERSION + 1] {
+            *old.last_mut().unwrap() = v;
+            assert_eq!(err(&old), CombatError::InvalidMigration.into());
+        }
+    }
+
+    #[test]
+    fn dual_capstone() {
+        let mut a = new_player();
+        a.attack_power = 100;
+        let v = new_player();
+        let t = TalentTableValues::default();
+        for bps in [1000i16, -1000] {
+            let cfg = ArenaConfig { dual_capstone_bps: bps, max_capstones: 5, ..ArenaConfig::default() };
+            let mut p = a.clone();
+            assert_eq!(compute_hit_damage(&p, &v, &cfg, &t, 0, false), 100);
+            assert_eq!(effective_max_health(&p, &cfg), 100);
+            // One capstone (Berserker is inactive at full HP): no adjustment
+            p.talent_frenzy = 1;
+            assert_eq!(compute_hit_damage(&p, &v, &cfg, &t, 0, false), 100);
+            assert_eq!(effective_max_health(&p, &cfg), 100);
+            // Second capstone
+            p.talent_momentum = 1;
+            let want = (100 * (10000 + bps as i32) / 10000) as u32;
+            assert_eq!(compute_hit_damage(&p, &v, &cfg, &t, 0, false) as u32, want);
+            assert_eq!(effective_max_health(&p, &cfg), want);
+            // Default off
+            assert_eq!(compute_hit_damage(&p, &v, &ArenaConfig::default(), &t, 0, false), 100);
+        }
+    }
+
+    #[test]
+    fn multi_kill_bookkeeping() {
+        let mut arena = test_arena();
+        let mut a = new_player();
+        // One attack: the primary target plus two AoE kills
+        let mut vs: Vec<PlayerState> = (0..3).map(|_| new_player()).collect();
+        for v in vs.iter_mut() {
+            apply_death(v, &mut a, &mut arena, 100);
+        }
+        assert_eq!(a.kills, 3);
+        assert_eq!(a.current_streak, 3);
+        assert_eq!(arena.total_kills, 3);
+        assert_eq!(arena.unique_killers, 1);
+        assert_eq!(arena.alive_count, 1);
+        for v in &vs {
+            assert!(!v.is_alive);
+            assert_eq!(v.health, 0);
+            assert_eq!(v.deaths, 1);
+            assert_eq!(v.xp, XP_PER_DEATH);
+            assert!(v.respawn_at > 100 && v.respawn_pending);
+            assert_eq!(v.last_killed_by, a.wallet);
+        }
+    }
+
+    #[test]
+    fn death_xp_toggle() {
+        let mut arena = test_arena();
+        arena.config.death_xp_enabled = false;
+        let mut a = new_player();
+        let mut v = new_player();
+        v.xp = level_floor_xp(3) - 1;
+        for _ in 0..50 {
+            apply_death(&mut v, &mut a, &mut arena, 100);
+            v.is_alive = true;
+        }
+        // Dying never levels the victim while death XP is off
+        assert_eq!(v.xp, level_floor_xp(3) - 1);
+        assert_eq!(calc_level(v.xp), 2);
+        arena.config.death_xp_enabled = true;
+        apply_death(&mut v, &mut a, &mut arena, 100);
+        assert_eq!(calc_level(v.xp), 3);
+    }
+
+    #[test]
+    fn deterministic_rolls() {
+        let a = [1u8; 32];
+        let b = 42i64.to_le_bytes();
+        let r = deterministic_roll(&[&a, &b], 10000);
+        assert_eq!(r, deterministic_roll(&[&a, &b], 10000));
+        assert!(r < 10000);
+        // The seed covers the concatenated bytes, not how they are split
+        assert_eq!(deterministic_seed(&[&a[..16], &a[16..], &b]), deterministic_seed(&[&a, &b]));
+        assert_ne!(deterministic_seed(&[&a, &43i64.to_le_bytes()]), deterministic_seed(&[&a, &b]));
+        assert_eq!(deterministic_roll(&[&a], 0), 0);
+        let mut buckets = [0u32; 10];
+        for i in 0..100_000u64 {
+            buckets[deterministic_roll(&[&i.to_le_bytes()], 10) as usize] += 1;
+        }
+        assert!(buckets.iter().all(|&c| (9_000..11_000).contains(&c)));
+
+        // Crit seeds are reproducible and move with the slot
+        let (x, y) = (new_player(), new_player());
+        assert_eq!(crit_seed(&x, &y, 100, 7), crit_seed(&x, &y, 100, 7));
+        assert_ne!(crit_seed(&x, &y, 100, 7), crit_seed(&x, &y, 100, 8));
+        assert_ne!(crit_seed(&x, &y, 100, 7), crit_seed(&y, &x, 100, 7));
+    }
+
+    #[test]
+    fn defense_scales() {
+        let mut a = new_player();
+        a.attack_power = 200;
+        let mut v = new_player();
+        v.talent_iron_skin = 5;
+        let def = TalentTableValues::default();
+        // Rank 5 Armor: 24% at full scale, 12% at half, none at zero
+        assert_eq!(compute_hit_damage(&a, &v, &ArenaConfig::default(), &def, 0, false), 152);
+        let half = ArenaConfig { armor_scale_bps: 5000, ..ArenaConfig::default() };
+        assert_eq!(compute_hit_damage(&a, &v, &half, &def, 0, false), 176);
+        let off = ArenaConfig { armor_scale_bps: 0, ..ArenaConfig::default() };
+        assert_eq!(compute_hit_damage(&a, &v, &off, &def, 0, false), 200);
+
+        let mut p = new_player();
+        p.health_level = 5;
+        p.talent_heavy_hitter = 5; // Iron Skin +30%
+        sync_stats_to_levels(&mut p, &ArenaConfig::default());
+        for (bps, want) in [(10000u16, 140u32 * 13 / 10), (5000, 140 * 115 / 100), (20000, 140 * 16 / 10), (0, 140)] {
+            let cfg = ArenaConfig { iron_skin_scale_bps: bps, ..ArenaConfig::default() };
+            assert_eq!(effective_max_health(&p, &cfg), want);
+        }
+        assert!(ArenaConfig { armor_scale_bps: 20001, ..ArenaConfig::default() }.validate().is_err());
+        assert!(ArenaConfig { iron_skin_scale_bps: 20001, ..ArenaConfig::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn migration_carries_cooldowns() {
+        let mut p = new_player();
+        p.ult_ready_at = 500;
+        p.dash_ready_at = 120;
+        p.heal_ready_at = 90;
+        p.set_ability_cooldown(ABILITY_HEAL, 200);
+        carry_legacy_cooldowns(&mut p);
+        assert!(!p.is_ability_ready(ABILITY_ULT, 499));
+        assert!(p.is_ability_ready(ABILITY_ULT, 500));
+        assert!(!p.is_ability_ready(ABILITY_DASH, 119));
+        // A newer cooldown already in the slot wins
+        assert!(!p.is_ability_ready(ABILITY_HEAL, 199));
+        assert_eq!((p.ult_ready_at, p.dash_ready_at, p.heal_ready_at), (0, 0, 0));
+        let before = p.ability_cooldowns;
+        carry_legacy_cooldowns(&mut p);
+        assert_eq!(p.ability_cooldowns, before);
+    }
+
+    #[test]
+    fn max_total_points_cap() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(100);
+        let cfg = ArenaConfig { max_total_points: 10, ..ArenaConfig::default() };
+        assert_eq!(talent_budget(100, &cfg), 10);
+        assert_eq!(talent_budget(100, &ArenaConfig::default()), calc_talent_points(100));
+        while let Some(slot) = (0..25u8).find(|&s| check_talent_allocation(&p, s, &cfg).is_ok()) {
+            p.set_talent(slot, p.get_talent(slot) + 1);
+        }
+        assert_eq!(p.total_talent_points_spent(), 10);
+        assert!((0..25u8).all(|s| matches!(check_talent_allocation(&p, s, &cfg), Err(CombatError::NoTalentPoints))));
+        // Without the cap the same level-100 player keeps going
+        assert!((0..25u8).any(|s| check_talent_allocation(&p, s, &ArenaConfig::default()).is_ok()));
+    }
+
+    #[test]
+    fn capstone_rank_corruption() {
+        let mut p = new_player();
+        p.xp = level_floor_xp(100);
+        let cfg = ArenaConfig::default();
+        for slot in 0..4u8 {
+            p.set_talent(slot, 1);
+        }
+        p.set_talent(4, 5); // capstone above MAX_TALENT_RANK_CAPSTONE
+        assert_eq!(lookup_bps(p.get_talent(4), &[1, 2, 3]), 0);
+        assert!(matches!(check_talent_allocation(&p, 4, &cfg), Err(CombatError::TalentRankCorrupt)));
+        assert_eq!(sanitize_talent_ranks(&mut p), 1);
+        assert!((0..25u8).all(|s| p.get_talent(s) <= max_rank_for_talent(s)));
+        assert_eq!(p.get_talent(4), MAX_TALENT_RANK_CAPSTONE);
+        assert!(matches!(check_talent_allocation(&p, 4, &cfg), Err(CombatError::TalentMaxed)));
+        assert_eq!(sanitize_talent_ranks(&mut p), 0);
+    }
+
+    #[test]
+    fn comeback_buff_hp() {
+        let on = ArenaConfig { comeback_buff: true, comeback_level_gap: 10, ..ArenaConfig::default() };
+        assert_eq!(comeback_bps(50, 60, &on), 0);
+        assert_eq!(comeback_bps(45, 60, &on), 1000);
+        assert_eq!(comeback_bps(1, 100, &on), COMEBACK_MAX_BPS);
+        assert_eq!(comeback_bps(80, 60, &on), 0);
+
+        let mut behind = new_player();
+        let mut caught_up = behind.clone();
+        behind.comeback_bps = comeback_bps(1, 100, &on) as u16;
+        caught_up.comeback_bps = comeback_bps(95, 100, &on) as u16;
+        assert_eq!(effective_max_health(&caught_up, &on), 100);
+        assert_eq!(effective_max_health(&behind, &on), 130);
+        // The stored bonus is inert while the arena has the buff off
+        assert_eq!(effective_max_health(&behind, &ArenaConfig::default()), 100);
+    }
+
+    #[test]
+    fn commit_receipt_seq() {
+        // Sized with a winner set, the only variable-length field
+        let mut arena = Arena { winner: Some(Pubkey::new_unique()), ..test_arena() };
+        assert_eq!(ARENA_SPACE, 8 + arena.try_to_vec().unwrap().len());
+        assert_eq!(arena.event_seq, 0);
+        for _ in 0..3 {
+            bump_event_seq(&mut arena);
+        }
+        assert_eq!(arena.event_seq, 3);
+    }
+
+    #[test]
+    fn distance_falloff() {
+        let def = ArenaConfig::default();
+        for b in 0..FALLOFF_BUCKETS as u8 {
+            assert_eq!(apply_falloff(50, b, &def).unwrap(), 50);
+        }
+        assert!(apply_falloff(50, FALLOFF_BUCKETS as u8, &def).is_err());
+        let cfg = ArenaConfig { falloff_table: [10000, 9000, 8000, 7000, 6000, 5000, 4000, 1], ..ArenaConfig::default() };
+        assert!(cfg.validate().is_ok());
+        assert_eq!(apply_falloff(50, 0, &cfg).unwrap(), 50);
+        assert_eq!(apply_falloff(50, 2, &cfg).unwrap(), 40);
+        assert_eq!(apply_falloff(50, 5, &cfg).unwrap(), 25);
+        assert_eq!(apply_falloff(50, 6, &cfg).unwrap(), 20);
+        // A landed hit never falls off to nothing; a zero hit stays zero
+        assert_eq!(apply_falloff(50, 7, &cfg).unwrap(), 1);
+        assert_eq!(apply_falloff(1, 5, &cfg).unwrap(), 1);
+        assert_eq!(apply_falloff(0, 5, &cfg).unwrap(), 0);
+        assert!(apply_falloff(0, FALLOFF_BUCKETS as u8, &cfg).is_err());
+        assert!(ArenaConfig { falloff_table: [0; FALLOFF_BUCKETS], ..ArenaConfig::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn respawn_after_dead_changes() {
+        let cfg = ArenaConfig::default();
+        let mut p = new_player();
+        p.xp = level_floor_xp(30);
+        p.is_alive = false;
+        p.health = 0;
+        // Iron Skin (slot 1) bought while dead
+        p.set_talent(0, 1);
+        for _ in 0..5 {
+            assert!(check_talent_allocation(&p, 1, &cfg).is_ok());
+            p.set_talent(1, p.get_talent(1) + 1);
+        }
+        // Health level raised while dead, max_health left stale
+        p.health_level = 5;
+        assert_eq!(p.max_health, 100);
+        let hp = respawn_health(&mut p, &cfg);
+        assert_eq!(p.max_health, 140);
+        assert_eq!(hp as u32, 140 * 13 / 10);
+        assert_eq!(hp as u32, effective_max_health(&p, &cfg));
+    }
+
+    #[test]
+    fn talents_disabled_mode() {
+        let off = ArenaConfig { talents_disabled: true, tree_bonus_threshold: 1, ..ArenaConfig::default() };
+        let on = ArenaConfig { tree_bonus_threshold: 1, ..ArenaConfig::default() };
+        let mut a = new_player();
+        a.xp = level_floor_xp(100);
+        let mut v = new_player();
+        let bare_a = a.clone();
+        let bare_v = v.clone();
+        for slot in 0..25u8 {
+            a.set_talent(slot, max_rank_for_talent(slot));
+            v.set_talent(slot, max_rank_for_talent(slot));
+        }
+        let tables = TalentTableValues::default();
+        assert_eq!(
+            compute_hit_damage(&a, &v, &off, &tables, 0, false),
+            compute_hit_damage(&bare_a, &bare_v, &off, &tables, 0, false)
+        );
+        assert_eq!(effective_max_health(&v, &off), 100);
+        assert!(effective_max_health(&v, &on) > 100);
+
+        // Sustain: no lifesteal, no counter
+        let eff_max = (100, 100);
+        let mut hurt = a.clone();
+        hurt.health = 50;
+        assert_eq!(apply_sustain(&mut hurt, &v, 80, 10, &off, &tables, eff_max, 0, 0), (0, 0));
+        assert_eq!(hurt.health, 50);
+        assert!(apply_lifesteal(&mut hurt.clone(), 80, 100, &on) > 0);
+
+        // Kill XP: no Experience bonus
+        assert_eq!(calc_kill_xp(&a, 10, &off), calc_kill_xp(&bare_a, 10, &off));
+        assert!(calc_kill_xp(&a, 10, &on) > calc_kill_xp(&bare_a, 10, &on));
+
+        // Regen: only the base rate
+        let regen_cfg = |talents_disabled| ArenaConfig {
+            talents_disabled,
+            out_of_combat_regen_per_sec: 5,
+            ..ArenaConfig::default()
+        };
+        let bare_hurt = PlayerState { health: 50, ..bare_a.clone() };
+        let now = ArenaConfig::default().out_of_combat_secs as i64 + 2;
+        assert_eq!(
+            out_of_combat_regen(&hurt, &regen_cfg(true), 100, now),
+            out_of_combat_regen(&bare_hurt, &regen_cfg(true), 100, now)
+        );
+        assert_ne!(
+            out_of_combat_regen(&hurt, &regen_cfg(false), 100, now),
+            out_of_combat_regen(&bare_hurt, &regen_cfg(false), 100, now)
+        );
+
+        assert!(matches!(check_talent_allocation(&bare_a, 0, &off), Err(CombatError::TalentsDisabled)));
+        assert!(check_talent_allocation(&bare_a, 0, &on).is_ok());
+        let mut ranks = [0u8; 25];
+        assert!(validate_loadout(&ranks, 100, &off).is_ok());
+        ranks[0] = 1;
+        assert!(validate_loadout(&ranks, 100, &off).is_err());
+    }
+
+    #[test]
+    fn duelists_only_fight_each_other() {
+        let duel = Pubkey::new_unique();
+        let free = new_player();
+        let a = PlayerState { duel, ..new_player() };
+        let b = PlayerState { duel, ..new_player() };
+        let other = PlayerState { duel: Pubkey::new_unique(), ..new_player() };
+        assert!(can_fight(&free, &new_player()));
+        assert!(can_fight(&a, &b) && can_fight(&b, &a));
+        assert!(!can_fight(&a, &free) && !can_fight(&free, &a));
+        assert!(!can_fight(&a, &other));
+    }
+
+    #[test]
+    fn back_to_back_attacks_one_death() {
+        let mut arena = test_arena();
+        let mut a = new_player();
+        let mut v = new_player();
+        // Two lethal volleys land in the same batch; only the first resolves
+        for _ in 0..2 {
+            if can_be_hit(&v, 100) {
+                apply_death(&mut v, &mut a, &mut arena, 100);
+            }
+        }
+        assert_eq!((v.deaths, a.kills, arena.total_kills), (1, 1, 1));
+        assert_eq!(arena.alive_count, 3);
+        // The tombstone holds even if the alive flag were flipped back early
+        v.is_alive = true;
+        assert!(!can_be_hit(&v, 100 + DEATH_GRACE_SECS - 1));
+        assert!(can_be_hit(&v, 100 + DEATH_GRACE_SECS));
+    }
+
+    #[test]
+    fn level_20_start() {
+        let cfg = ArenaConfig { start_level: 20, start_xp: level_floor_xp(20), ..ArenaConfig::default() };
+        assert!(cfg.validate().is_ok());
+        assert!(ArenaConfig { start_level: 21, ..cfg.clone() }.validate().is_err());
+        let mut p = new_player();
+        apply_start_profile(&mut p, &cfg);
+        let check = |p: &PlayerState| {
+            assert_eq!(calc_level(p.xp), 20);
+            assert_eq!((p.health_level, p.attack_level), (20, 20));
+            assert_eq!(p.max_health, BASE_HEALTH + 19 * HEALTH_PER_STAT_LEVEL);
+            assert_eq!(p.attack_power, BASE_ATTACK + 19 * ATTACK_PER_STAT_LEVEL);
+            assert_eq!(p.health as u32, effective_max_health(p, &cfg));
+        };
+        check(&p);
+        // A reset, even of a dead player, lands back on the same start
+        p.xp = level_floor_xp(60);
+        p.health_level = 40;
+        p.is_alive = false;
+        p.health = 0;
+        reset_to_base(&mut p);
+        apply_start_profile(&mut p, &cfg);
+        check(&p);
+    }
+
+    #[test]
+    fn soft_hit_cap_100_vs_400() {
+        let linear = ArenaConfig::default();
+        let soft = ArenaConfig { soft_hit_cap: 100, ..ArenaConfig::default() };
+        assert_eq!(effective_hits(100, &linear), 100);
+        assert_eq!(effective_hits(400, &linear), 400);
+        assert_eq!(effective_hits(100, &soft), 100);
+        // 2 * sqrt(100 * 400) - 100
+        assert_eq!(effective_hits(400, &soft), 300);
+        assert_eq!(calc_total_damage(10, 100, &linear), 1000);
+        assert_eq!(calc_total_damage(10, 100, &soft), 1000);
+        assert_eq!(calc_total_damage(10, 400, &linear), 4000);
+        assert_eq!(calc_total_damage(10, 400, &soft), 3000);
+
+        // Counter Attack follows the same curve
+        let tables = TalentTableValues::default();
+        let mut victim = new_player();
+        victim.talent_absorb = 5;
+        victim.attack_power = 20;
+        let counter = |hits: u16, cfg: &ArenaConfig| {
+            let mut a = PlayerState { health: 60000, ..new_player() };
+            apply_counter(&mut a, &victim, hits, cfg, &tables, (60000, 100), 0, 0) as u32
+        };
+        assert_eq!(counter(100, &soft), counter(100, &linear));
+        let (full, thinned) = (counter(400, &linear), counter(400, &soft));
+        assert!(full > 0);
+        assert!(thinned.abs_diff(full * 3 / 4) <= 1);
+    }
+
+    #[test]
+    fn second_wind_partial_respawn() {
+        let cfg = ArenaConfig { respawn_hp_bps: 5000, second_wind: true, ..ArenaConfig::default() };
+        let mut p = new_player();
+        assert_eq!(respawn_health(&mut p, &cfg), 50);
+        // Armor rank 3: 50% + 15%
+        p.talent_iron_skin = 3;
+        assert_eq!(respawn_health(&mut p, &cfg), 65);
+        // Capped at full HP
+        let high = ArenaConfig { respawn_hp_bps: 8000, ..cfg.clone() };
+        p.talent_iron_skin = 5;
+        assert_eq!(respawn_health(&mut p, &high), 100);
+        // Off without the arena flag, and never from Regeneration
+        assert_eq!(respawn_health(&mut p, &ArenaConfig { second_wind: false, ..cfg.clone() }), 50);
+        let mut regen = new_player();
+        regen.talent_regeneration = 5;
+        assert_eq!(respawn_health(&mut regen, &cfg), 50);
+    }
+
+    #[test]
+    fn elimination_blocks_reviving_reset() {
+        let mut arena = test_arena();
+        arena.config.elimination_mode = true;
+        let mut p = new_player();
+        assert!(check_reset(&p, &arena).is_ok());
+        p.is_alive = false;
+        assert!(check_reset(&p, &arena).is_err());
+        // Between rounds, or outside elimination mode, the dead may reset
+        arena.is_active = false;
+        assert!(check_reset(&p, &arena).is_ok());
+        arena.is_active = true;
+        arena.config.elimination_mode = false;
+        assert!(check_reset(&p, &arena).is_ok());
+    }
+
+    #[test]
+    fn duelists_cannot_reset() {
+        let arena = test_arena();
+        let mut p = new_player();
+        p.duel = Pubkey::new_unique();
+        assert!(check_reset(&p, &arena).is_err());
+        p.duel = Pubkey::default();
+        assert!(check_reset(&p, &arena).is_ok());
+    }
 }
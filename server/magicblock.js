@@ -24,10 +24,21 @@ const ER_VALIDATOR = new PublicKey(
 const BASE_RPC = process.env.SOLANA_BASE_RPC || 'https://api.devnet.solana.com';
 const ER_RPC = process.env.MAGICBLOCK_ER_RPC || 'https://devnet.magicblock.app';
 const ER_WS = process.env.MAGICBLOCK_ER_WS || 'wss://devnet.magicblock.app';
+// Which arena this server runs; 0 is the original singleton arena
+const ARENA_ID = parseInt(process.env.ARENA_ID || '0', 10);
 
 // PDA Seeds (must match Rust program)
 const ARENA_SEED = Buffer.from('arena');
 const PLAYER_SEED = Buffer.from('player_v2');
+const TALENT_TABLES_SEED = Buffer.from('talent_tables');
+
+// Extra arena seed (must match arena_id_seed): none for arena 0, u16 LE otherwise
+function arenaIdSeed(arenaId) {
+  if (arenaId === 0) return [];
+  const buf = Buffer.alloc(2);
+  buf.writeUInt16LE(arenaId);
+  return [buf];
+}
 
 // On-chain uses u16 integers for damage/attack (BASE_ATTACK=10).
 // Local game uses floats (bulletDamage=0.1).  Scale factor = 100.
@@ -77,6 +88,8 @@ class MagicBlockService {
     // Arena PDA (derived deterministically)
     this.arenaPda = null;
     this.arenaBump = null;
+    // Talent tables PDA; the program reads it even before it is initialized
+    this.talentTablesPda = null;
 
     // Anchor programs (base layer and ER)
     this.baseProgram = null;
@@ -194,10 +207,14 @@ class MagicBlockService {
 
       // Derive arena PDA
       [this.arenaPda, this.arenaBump] = PublicKey.findProgramAddressSync(
-        [ARENA_SEED],
+        [ARENA_SEED, ...arenaIdSeed(ARENA_ID)],
+        COMBAT_PROGRAM_ID
+      );
+      console.log(`MagicBlock: Arena ${ARENA_ID} PDA:`, this.arenaPda.toBase58());
+      [this.talentTablesPda] = PublicKey.findProgramAddressSync(
+        [TALENT_TABLES_SEED, this.arenaPda.toBuffer()],
         COMBAT_PROGRAM_ID
       );
-      console.log('MagicBlock: Arena PDA:', this.arenaPda.toBase58());
 
       // Check if arena already exists on base layer
       const arenaAccount = await this.baseConnection.getAccountInfo(this.arenaPda);
@@ -215,7 +232,7 @@ class MagicBlockService {
         // Initialize arena on base layer
         console.log('MagicBlock: Initializing Arena on base layer...');
         const tx = await this.baseProgram.methods
-          .initArena()
+          .initArena(ARENA_ID)
           .accounts({
             arena: this.arenaPda,
             authority: this.serverKeypair.publicKey,
@@ -355,7 +372,7 @@ class MagicBlockService {
       );
 
       const tx = await this.baseProgram.methods
-        .delegateArena()
+        .delegateArena(ARENA_ID)
         .accounts({
           payer: this.serverKeypair.publicKey,
           bufferArena: bufferPda,
@@ -457,11 +474,12 @@ class MagicBlockService {
         return playerInfo;
       }
 
-      // Register player on base layer (no arena needed)
+      // Register player on base layer into this server's arena
       const tx = await this.baseProgram.methods
         .registerPlayer(walletPubkey)
         .accounts({
           playerState: playerPda,
+          arena: this.arenaPda,
           authority: this.serverKeypair.publicKey,
           systemProgram: SystemProgram.programId,
         })
@@ -487,7 +505,7 @@ class MagicBlockService {
 
   // ─── Combat (runs on ER) ─────────────────────────────────────────
 
-  async processAttack(attackerAddress, victimAddress, hitCount) {
+  async processAttack(attackerAddress, victimAddress, hitCount, distanceBucket = 0) {
     if (!this.ready || !this.arenaDelegated) return null;
 
     const attacker = this.playerMap.get(attackerAddress);
@@ -508,11 +526,12 @@ class MagicBlockService {
       // Server sends hit count — the chain computes damage from on-chain talent state.
       // The server CANNOT dictate damage amounts.
       const tx = await this.erProgram.methods
-        .processAttack(clampedHits)
+        .processAttack(clampedHits, distanceBucket)
         .accounts({
           attacker: attacker.playerPda,
           victim: victim.playerPda,
           arena: this.arenaPda,
+          talentTables: this.talentTablesPda,
         })
         .rpc();
 
@@ -578,6 +597,7 @@ class MagicBlockService {
         .respawnPlayer()
         .accounts({
           playerState: player.playerPda,
+          arena: this.arenaPda,
         })
         .rpc();
 
@@ -604,6 +624,7 @@ class MagicBlockService {
         .upgradeStat(statType)
         .accounts({
           playerState: player.playerPda,
+          arena: this.arenaPda,
         })
         .rpc();
 
@@ -635,6 +656,7 @@ class MagicBlockService {
         .allocateTalent(talentId)
         .accounts({
           playerState: player.playerPda,
+          arena: this.arenaPda,
         })
         .rpc();
 
@@ -662,6 +684,7 @@ class MagicBlockService {
         .resetTalents()
         .accounts({
           playerState: player.playerPda,
+          arena: this.arenaPda,
         })
         .rpc();
 
@@ -683,6 +706,7 @@ class MagicBlockService {
         .resetPlayer()
         .accounts({
           playerState: playerPda,
+          arena: this.arenaPda,
         })
         .rpc();
 
@@ -951,6 +975,7 @@ class MagicBlockService {
         .accounts({
           payer: this.serverKeypair.publicKey,
           playerState: player.playerPda,
+          arena: this.arenaPda,
           magicProgram: new PublicKey('Magic11111111111111111111111111111111111111'),
           magicContext: new PublicKey('MagicContext1111111111111111111111111111111'),
         })